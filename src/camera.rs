@@ -1,10 +1,246 @@
 use wgpu::util::DeviceExt;
-use winit::{event::{ElementState, KeyEvent, WindowEvent}, keyboard::{KeyCode, PhysicalKey}};
+use winit::{event::{ElementState, KeyEvent, MouseScrollDelta, WindowEvent}, keyboard::{KeyCode, PhysicalKey}};
+
+use crate::path_tracing::{chunk::CHUNK_SIZE, quaternion::Quaternion, vector_funcs::{add_vector, cross_vector, normalize_vector, scale_vector, sub_vector, vector_length}};
+
+//Where a camera is and what it is looking at, this is what gets stored in camera files (.ron).
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub struct CameraPose {
+    pub origin: [f32; 3],
+    pub looking_at: [f32; 3],
+    #[serde(default = "default_fov_y")]
+    pub fov_y: f32,
+    #[serde(default)]
+    pub aperture_radius: f32,
+    #[serde(default = "default_focus_distance")]
+    pub focus_distance: f32,
+    #[serde(default)]
+    pub autofocus: bool, //Overrides focus_distance with the distance to the voxel in the center of the screen.
+    #[serde(default)]
+    pub projection: Projection,
+    #[serde(default = "default_ortho_height")]
+    pub ortho_height: f32,
+}
+
+fn default_fov_y() -> f32 {
+    Camera::DEFAULT_FOV_Y
+}
+
+fn default_focus_distance() -> f32 {
+    Camera::DEFAULT_FOCUS_DISTANCE
+}
+
+fn default_ortho_height() -> f32 {
+    Camera::DEFAULT_ORTHO_HEIGHT
+}
+
+//How the camera maps pixels to rays.
+//The discriminants are uploaded to the path tracer as-is, so keep them in sync with path_tracer.wgsl.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+pub enum Projection {
+    #[default]
+    Perspective = 0,
+    Orthographic = 1, //Parallel rays, ortho_height decides how much of the world fits on screen. Looking straight down gives a map.
+    Equirectangular = 2, //360 degree panorama around the camera, use a 2:1 image for square pixels. Path tracer only.
+}
+
+impl Projection {
+    pub const ALL: [Projection; 3] = [
+        Projection::Perspective,
+        Projection::Orthographic,
+        Projection::Equirectangular,
+    ];
+
+    pub fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+
+    //Unknown values fall back to perspective, like the default case in the shader.
+    pub fn from_u32(value: u32) -> Self {
+        Self::ALL.get(value as usize).copied().unwrap_or_default()
+    }
+}
+
+//Same view as the default camera of PTRender.
+impl Default for CameraPose {
+    fn default() -> Self {
+        Self {
+            origin: [0.0, 5.0, 0.0],
+            looking_at: [0.0, 0.0, 0.0],
+            fov_y: default_fov_y(),
+            aperture_radius: 0.0,
+            focus_distance: default_focus_distance(),
+            autofocus: false,
+            projection: Projection::default(),
+            ortho_height: default_ortho_height(),
+        }
+    }
+}
+
+impl CameraPose {
+    pub fn load(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        crate::load_ron(path)
+    }
+}
+
+//The one camera both renderers look through, z is up.
+//The path tracer turns it into rays (see tracing_camera.rs), the rasterizer into a view-projection matrix.
+//The orientation is stored as yaw/pitch/roll, the basis vectors are rebuilt from those with update_basis.
+pub struct Camera {
+    pub origin: [f32; 3],
+    pub yaw: f32, //Radians counterclockwise around world up (z), 0 looks along x.
+    pub pitch: f32, //Radians above the horizon.
+    pub roll: f32, //Radians around forward_vec, only changed by rotate_camera_roll.
+    pub forward_vec: [f32; 3],
+    pub left_vec: [f32; 3],
+    pub up_vec: [f32; 3],
+    pub aspect_ratio: f32,
+    pub fov_y: f32, //Vertical field of view in degrees.
+    pub aperture_radius: f32, //0 gives a pinhole camera where everything is sharp.
+    pub focus_distance: f32, //Distance along forward_vec of the plane that is in focus.
+    pub projection: Projection,
+    pub ortho_height: f32, //World units that fit vertically on screen with the orthographic projection.
+    pub screen_size: [usize; 2],
+}
+
+impl Camera {
+    pub const DEFAULT_FOV_Y: f32 = 40.0;
+    pub const MIN_FOV_Y: f32 = 5.0;
+    pub const MAX_FOV_Y: f32 = 120.0;
+    pub const DEFAULT_FOCUS_DISTANCE: f32 = 10.0;
+    pub const DEFAULT_ORTHO_HEIGHT: f32 = CHUNK_SIZE as f32;
+    //From a few voxels up to a map of a lot of chunks.
+    pub const MIN_ORTHO_HEIGHT: f32 = 4.0;
+    pub const MAX_ORTHO_HEIGHT: f32 = 4096.0;
+    //Staying away from straight up/down keeps the mouse from flipping the camera over.
+    pub const MAX_PITCH: f32 = 89.0 * std::f32::consts::PI / 180.0;
+    //Clip planes of the rasterizer, the path tracer has none.
+    pub const ZNEAR: f32 = 0.1;
+    pub const ZFAR: f32 = 1000.0;
+
+    pub fn new(
+        origin: [f32; 3],
+        fov_y: f32,
+        screen_size: [usize; 2],
+        looking_at: [f32; 3]
+    ) -> Self {
+        let looking_at_n = normalize_vector(&[looking_at[0] - origin[0], looking_at[1] - origin[1], looking_at[2] - origin[2]]);
+        //Straight up or down is allowed here, yaw then decides which way the screen is turned.
+        let yaw = looking_at_n[1].atan2(looking_at_n[0]);
+        let pitch = looking_at_n[2].clamp(-1.0, 1.0).asin();
+
+        let aspect_ratio = screen_size[0] as f32 / screen_size[1] as f32;
+
+        let mut camera = Self {
+            origin,
+            yaw,
+            pitch,
+            roll: 0.0,
+            forward_vec: [0.0; 3],
+            left_vec: [0.0; 3],
+            up_vec: [0.0; 3],
+            aspect_ratio,
+            fov_y,
+            aperture_radius: 0.0,
+            focus_distance: Self::DEFAULT_FOCUS_DISTANCE,
+            projection: Projection::default(),
+            ortho_height: Self::DEFAULT_ORTHO_HEIGHT,
+            screen_size,
+        };
+        camera.update_basis();
+        camera
+    }
+
+    //Autofocus is not applied here, as that needs the scene.
+    pub fn from_pose(pose: &CameraPose, screen_size: [usize; 2]) -> Self {
+        let mut camera = Self::new(pose.origin, pose.fov_y, screen_size, pose.looking_at);
+        camera.aperture_radius = pose.aperture_radius;
+        camera.focus_distance = pose.focus_distance;
+        camera.projection = pose.projection;
+        camera.ortho_height = pose.ortho_height;
+        camera
+    }
+
+    //left_vec only depends on yaw so it always stays level with the horizon, unless roll is used.
+    pub fn update_basis(&mut self) {
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
+
+        self.forward_vec = [cos_pitch * cos_yaw, cos_pitch * sin_yaw, sin_pitch];
+        self.left_vec = [-sin_yaw, cos_yaw, 0.0];
+        self.up_vec = cross_vector(&self.forward_vec, &self.left_vec);
+
+        if self.roll != 0.0 {
+            let q = Quaternion::from_axis_angle(self.forward_vec, self.roll);
+            self.left_vec = q.rotate_vector(self.left_vec);
+            self.up_vec = q.rotate_vector(self.up_vec);
+        }
+    }
+
+    //Rotation that takes x/y/z to forward_vec/left_vec/up_vec.
+    pub fn orientation(&self) -> Quaternion {
+        Quaternion::from_euler(self.yaw, self.pitch, self.roll)
+    }
+
+    //Inverse of orientation, pitch is not clamped so played back paths may look straight up or down.
+    pub fn set_orientation(&mut self, orientation: Quaternion) {
+        [self.yaw, self.pitch, self.roll] = orientation.to_euler();
+        self.update_basis();
+    }
+
+    //Turns counterclockwise around world up, so the horizon never tilts.
+    pub fn rotate_camera_yaw(&mut self, rad: f32) {
+        self.yaw = (self.yaw + rad).rem_euclid(std::f32::consts::TAU);
+        self.update_basis();
+    }
+
+    //Positive looks up, clamped to MAX_PITCH.
+    pub fn rotate_camera_pitch(&mut self, rad: f32) {
+        self.pitch = (self.pitch + rad).clamp(-Self::MAX_PITCH, Self::MAX_PITCH);
+        self.update_basis();
+    }
+
+    //Nothing rolls the camera on its own, this has to be called explicitly and stays until it is rotated back.
+    pub fn rotate_camera_roll(&mut self, rad: f32) {
+        self.roll += rad;
+        self.update_basis();
+    }
+
+    //Shows the same view as the rays of the path tracer.
+    pub fn view_projection_matrix(&self) -> cgmath::Matrix4<f32> {
+        let view = cgmath::Matrix4::look_to_rh(
+            cgmath::Point3::from(self.origin),
+            cgmath::Vector3::from(self.forward_vec),
+            cgmath::Vector3::from(self.up_vec),
+        );
+
+        let proj = match self.projection {
+            Projection::Orthographic => {
+                let half_height = self.ortho_height * 0.5;
+                let half_width = half_height * self.aspect_ratio;
+                cgmath::ortho(-half_width, half_width, -half_height, half_height, 0.0, Self::ZFAR)
+            }
+            //Straight edges can't be bent into a panorama, so the rasterizer shows perspective instead.
+            Projection::Perspective | Projection::Equirectangular => {
+                cgmath::perspective(cgmath::Deg(self.fov_y), self.aspect_ratio, Self::ZNEAR, Self::ZFAR)
+            }
+        };
+
+        OPENGL_TO_WGPU_MATRIX * proj * view
+    }
+}
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct CameraUniform {
-    view_proj: [[f32; 4]; 4], 
+    view_proj: [[f32; 4]; 4],
+}
+
+impl Default for CameraUniform {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl CameraUniform {
@@ -15,61 +251,32 @@ impl CameraUniform {
         }
     }
 
-    pub fn update_view_proj(&mut self, camera: &CameraInner) {
-        self.view_proj = camera.build_view_projection_matrix().into();
+    pub fn update_view_proj(&mut self, camera: &Camera) {
+        self.view_proj = camera.view_projection_matrix().into();
     }
 }
 
+//Maps OpenGL's -1..1 depth range to the 0..1 wgpu expects. Matrix4::new takes columns, so this reads transposed.
 #[rustfmt::skip]
 pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
     1.0, 0.0, 0.0, 0.0,
     0.0, 1.0, 0.0, 0.0,
-    0.0, 0.0, 0.5, 0.5,
-    0.0, 0.0, 0.0, 1.0,
+    0.0, 0.0, 0.5, 0.0,
+    0.0, 0.0, 0.5, 1.0,
 );
 
-pub struct CameraInner {
-    eye: cgmath::Point3<f32>,
-    target: cgmath::Point3<f32>,
-    up: cgmath::Vector3<f32>,
-    aspect: f32,
-    fovy: f32,
-    znear: f32,
-    zfar: f32,
-}
-
-impl CameraInner {
-    fn build_view_projection_matrix(&self) -> cgmath::Matrix4<f32> {
-        let view = cgmath::Matrix4::look_at_rh(self.eye, self.target, self.up);
-
-        let proj = cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar);
-
-        OPENGL_TO_WGPU_MATRIX * proj * view
-    }
-}
-
-pub struct Camera {
-    pub camera_inner: CameraInner,
+//The gpu side of the camera for the rasterizer, the bind group shader.wgsl reads the view-projection matrix from.
+pub struct CameraBinding {
     pub camera_uniform: CameraUniform,
     pub camera_buffer: wgpu::Buffer,
     pub camera_bind_group_layout: wgpu::BindGroupLayout,
     pub camera_bind_group: wgpu::BindGroup
 }
 
-impl Camera {
-    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Self {
-        let inner_camera = CameraInner {
-            eye: (16.0, 16.0, 20.0).into(),
-            target: (16.0, 16.0, 0.0).into(),
-            up: cgmath::Vector3::unit_y(),
-            aspect: config.width as f32 / config.height as f32,
-            fovy: 45.0,
-            znear: 0.1,
-            zfar: 100.0,
-        };
-
+impl CameraBinding {
+    pub fn new(device: &wgpu::Device, camera: &Camera) -> Self {
         let mut camera_uniform = CameraUniform::new();
-        camera_uniform.update_view_proj(&inner_camera);
+        camera_uniform.update_view_proj(camera);
 
         let camera_buffer = device.create_buffer_init(
             &wgpu::util::BufferInitDescriptor {
@@ -107,40 +314,196 @@ impl Camera {
         });
 
         Self{
-            camera_inner: inner_camera,
-            camera_uniform: camera_uniform,
-            camera_buffer: camera_buffer,
-            camera_bind_group: camera_bind_group,
-            camera_bind_group_layout: camera_bind_group_layout,
+            camera_uniform,
+            camera_buffer,
+            camera_bind_group,
+            camera_bind_group_layout,
         }
+    }
 
-        
-    } 
+    pub fn update(&mut self, queue: &wgpu::Queue, camera: &Camera) {
+        self.camera_uniform.update_view_proj(camera);
+        queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[self.camera_uniform]));
+    }
+}
 
 
+//How the camera accelerates and slows down, speeds are in world units per second.
+//Loaded from movement.ron when it exists, fields left out keep their default.
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct MovementConfig {
+    pub max_speed: f32,
+    pub sprint_multiplier: f32,
+    pub acceleration: f32, //Per second, higher reaches max_speed sooner.
+    pub damping: f32, //Per second, higher comes to a stop sooner once the keys are let go.
 }
 
+impl Default for MovementConfig {
+    fn default() -> Self {
+        Self {
+            max_speed: 18.0,
+            sprint_multiplier: 3.0,
+            acceleration: 10.0,
+            damping: 8.0,
+        }
+    }
+}
 
+impl MovementConfig {
+    pub fn load(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        crate::load_ron(path)
+    }
+}
 
+//Free-fly controls for the camera, the same for both renderers.
 pub struct CameraController {
-    speed: f32,
-    is_forward_pressed: bool,
-    is_backward_pressed: bool,
-    is_left_pressed: bool,
-    is_right_pressed: bool,
+    pub sensitivity: f32,
+    pub movement: MovementConfig,
+    pub velocity: [f32; 3],
+    pub is_forward_pressed: bool,
+    pub is_backward_pressed: bool,
+    pub is_left_pressed: bool,
+    pub is_right_pressed: bool,
+    pub is_up_pressed: bool,
+    pub is_down_pressed: bool,
+    pub is_sprint_pressed: bool,
+    pub mouse_x_movement: f32,
+    pub mouse_y_movement: f32,
+    pub zoom_speed: f32, //Degrees of field of view per scrolled line.
+    pub scroll_movement: f32,
+    pub aperture_step: f32,
+    pub focus_step: f32,
+    pub autofocus: bool,
+    pub aperture_change: f32,
+    pub focus_change: f32,
+    pub refocus: bool, //Set when autofocus gets turned on, so it focuses without having to move first.
+}
+
+impl Default for CameraController {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl CameraController {
-    pub fn new(speed: f32) -> Self {
+    const STOP_SPEED: f32 = 0.01;
+
+    pub fn new() -> Self {
         Self {
-            speed,
+            sensitivity: 0.01,
+            movement: MovementConfig::default(),
+            velocity: [0.0; 3],
             is_forward_pressed: false,
             is_backward_pressed: false,
             is_left_pressed: false,
             is_right_pressed: false,
+            is_up_pressed: false,
+            is_down_pressed: false,
+            is_sprint_pressed: false,
+            mouse_x_movement: 0.0,
+            mouse_y_movement: 0.0,
+            zoom_speed: 2.0,
+            scroll_movement: 0.0,
+            aperture_step: 0.05,
+            focus_step: 0.5,
+            autofocus: false,
+            aperture_change: 0.0,
+            focus_change: 0.0,
+            refocus: false,
+        }
+    }
+
+    //Returns if the camera changed, the caller then has to upload it to whichever renderer is active.
+    //focus is only called for autofocus, with the moved camera, and gives the distance to focus on if there is anything to focus on.
+    pub fn update_camera(
+        &mut self,
+        camera: &mut Camera,
+        dt: std::time::Duration,
+        focus: impl FnOnce(&Camera) -> Option<f32>,
+    ) -> bool {
+        let dt = dt.as_secs_f32();
+
+        //Direction the held keys point in, forward and sideways follow the camera while vertical movement follows world up.
+        let axis = |positive: bool, negative: bool| positive as i32 as f32 - negative as i32 as f32;
+        let wish = add_vector(
+            &add_vector(
+                &scale_vector(&camera.forward_vec, axis(self.is_forward_pressed, self.is_backward_pressed)),
+                &scale_vector(&camera.left_vec, axis(self.is_left_pressed, self.is_right_pressed)),
+            ),
+            &[0.0, 0.0, axis(self.is_up_pressed, self.is_down_pressed)],
+        );
+
+        //Exponential smoothing towards the target velocity, so the feel doesn't depend on the frame rate.
+        let (target, rate) = if vector_length(&wish) > 0.0 {
+            let speed = self.movement.max_speed * if self.is_sprint_pressed { self.movement.sprint_multiplier } else { 1.0 };
+            (scale_vector(&normalize_vector(&wish), speed), self.movement.acceleration)
+        } else {
+            ([0.0; 3], self.movement.damping)
+        };
+        let blend = 1.0 - (-rate * dt).exp();
+        self.velocity = add_vector(&self.velocity, &scale_vector(&sub_vector(&target, &self.velocity), blend));
+
+        //Snap to a standstill, otherwise the camera keeps creeping and samples never get to accumulate.
+        if vector_length(&target) == 0.0 && vector_length(&self.velocity) < Self::STOP_SPEED {
+            self.velocity = [0.0; 3];
+        }
+
+        let mut changed = false;
+        if self.velocity != [0.0; 3] {
+            camera.origin = add_vector(&camera.origin, &scale_vector(&self.velocity, dt));
+            changed = true;
+        }
+
+        if self.mouse_x_movement != 0.0 {
+            camera.rotate_camera_yaw(-(self.mouse_x_movement * self.sensitivity));
+            self.mouse_x_movement = 0.0;
+            changed = true;
+        }
+
+        if self.mouse_y_movement != 0.0 {
+            camera.rotate_camera_pitch(-(self.mouse_y_movement * self.sensitivity));
+            self.mouse_y_movement = 0.0;
+            changed = true;
+        }
+
+        if self.scroll_movement != 0.0 {
+            if camera.projection == Projection::Orthographic {
+                //Every line scrolled shows 10% less of the world.
+                let ortho_height = camera.ortho_height * 0.9f32.powf(self.scroll_movement);
+                camera.ortho_height = ortho_height.clamp(Camera::MIN_ORTHO_HEIGHT, Camera::MAX_ORTHO_HEIGHT);
+            } else {
+                let fov_y = camera.fov_y - self.scroll_movement * self.zoom_speed;
+                camera.fov_y = fov_y.clamp(Camera::MIN_FOV_Y, Camera::MAX_FOV_Y);
+            }
+            self.scroll_movement = 0.0;
+            changed = true;
+        }
+
+        if self.aperture_change != 0.0 {
+            camera.aperture_radius = (camera.aperture_radius + self.aperture_change).max(0.0);
+            self.aperture_change = 0.0;
+            changed = true;
+        }
+
+        if self.focus_change != 0.0 && !self.autofocus {
+            camera.focus_distance = (camera.focus_distance + self.focus_change).max(self.focus_step);
+            changed = true;
+        }
+        self.focus_change = 0.0;
+
+        if self.autofocus && (changed || self.refocus) {
+            if let Some(distance) = focus(camera).filter(|&d| d != camera.focus_distance) {
+                camera.focus_distance = distance;
+                changed = true;
+            }
         }
+        self.refocus = false;
+
+        changed
     }
 
+
     pub fn process_events(&mut self, event: &WindowEvent) -> bool {
         match event {
             WindowEvent::KeyboardInput {
@@ -169,42 +532,51 @@ impl CameraController {
                         self.is_right_pressed = is_pressed;
                         true
                     }
+                    KeyCode::Space => {
+                        self.is_up_pressed = is_pressed;
+                        true
+                    }
+                    KeyCode::ShiftLeft | KeyCode::ShiftRight => {
+                        self.is_down_pressed = is_pressed;
+                        true
+                    }
+                    KeyCode::ControlLeft | KeyCode::ControlRight => {
+                        self.is_sprint_pressed = is_pressed;
+                        true
+                    }
+                    KeyCode::BracketLeft | KeyCode::BracketRight => {
+                        if is_pressed {
+                            let sign = if *keycode == KeyCode::BracketLeft { -1.0 } else { 1.0 };
+                            self.aperture_change += sign * self.aperture_step;
+                        }
+                        true
+                    }
+                    KeyCode::Comma | KeyCode::Period => {
+                        if is_pressed {
+                            let sign = if *keycode == KeyCode::Comma { -1.0 } else { 1.0 };
+                            self.focus_change += sign * self.focus_step;
+                        }
+                        true
+                    }
+                    KeyCode::KeyF => {
+                        if is_pressed {
+                            self.autofocus = !self.autofocus;
+                            self.refocus = self.autofocus;
+                        }
+                        true
+                    }
                     _ => false,
                 }
             }
+            WindowEvent::MouseWheel { delta, .. } => {
+                //Pixel deltas come from touchpads, roughly 20 pixels make up a line.
+                self.scroll_movement += match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 20.0,
+                };
+                true
+            }
             _ => false,
         }
     }
-
-    pub fn update_camera(&self, camera: &mut CameraInner) {
-        use cgmath::InnerSpace;
-        let forward = camera.target - camera.eye;
-        let forward_norm = forward.normalize();
-        let forward_mag = forward.magnitude();
-
-        // Prevents glitching when the camera gets too close to the
-        // center of the scene.
-        if self.is_forward_pressed && forward_mag > self.speed {
-            camera.eye += forward_norm * self.speed;
-        }
-        if self.is_backward_pressed {
-            camera.eye -= forward_norm * self.speed;
-        }
-
-        let right = forward_norm.cross(camera.up);
-
-        // Redo radius calc in case the forward/backward is pressed.
-        let forward = camera.target - camera.eye;
-        let forward_mag = forward.magnitude();
-
-        if self.is_right_pressed {
-            // Rescale the distance between the target and the eye so 
-            // that it doesn't change. The eye, therefore, still 
-            // lies on the circle made by the target and eye.
-            camera.eye = camera.target - (forward + right * self.speed).normalize() * forward_mag;
-        }
-        if self.is_left_pressed {
-            camera.eye = camera.target - (forward - right * self.speed).normalize() * forward_mag;
-        }
-    }
-}
\ No newline at end of file
+}
@@ -0,0 +1,192 @@
+use std::{io, path::Path, time::Duration};
+
+use crate::{camera::Camera, path_tracing::{quaternion::Quaternion, vector_funcs::{add_vector, scale_vector, sub_vector}}};
+
+//One recorded camera pose, time is in seconds since the recording started.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct CameraKeyframe {
+    pub time: f32,
+    pub origin: [f32; 3],
+    pub yaw: f32,
+    pub pitch: f32,
+    pub roll: f32,
+    pub fov_y: f32,
+}
+
+impl CameraKeyframe {
+    pub fn from_camera(camera: &Camera, time: f32) -> Self {
+        Self {
+            time,
+            origin: camera.origin,
+            yaw: camera.yaw,
+            pitch: camera.pitch,
+            roll: camera.roll,
+            fov_y: camera.fov_y,
+        }
+    }
+}
+
+//A flythrough to replay for benchmarks and trailers, stored as .ron like camera poses.
+//Keyframes have to be sorted by time.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct CameraPath {
+    pub keyframes: Vec<CameraKeyframe>,
+}
+
+impl CameraPath {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let camera_path: Self = crate::load_ron(path)?;
+        if camera_path.keyframes.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Camera path has no keyframes"));
+        }
+        //apply looks the segment up with a binary search, which only works on sorted times.
+        if !camera_path.keyframes.windows(2).all(|pair| pair[0].time <= pair[1].time) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Camera path keyframes aren't sorted by time"));
+        }
+        Ok(camera_path)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let contents = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()).map_err(io::Error::other)?;
+        std::fs::write(path, contents)
+    }
+
+    //Seconds from the first to the last keyframe.
+    pub fn duration(&self) -> f32 {
+        match (self.keyframes.first(), self.keyframes.last()) {
+            (Some(first), Some(last)) => last.time - first.time,
+            _ => 0.0,
+        }
+    }
+
+    //Moves the camera to where the path is at time seconds after its first keyframe.
+    //The origin follows a Catmull-Rom spline through the keyframes and the orientation is slerped,
+    //times outside of the path hold the first or last keyframe.
+    pub fn apply(&self, time: f32, camera: &mut Camera) {
+        let keyframes = &self.keyframes;
+        assert!(!keyframes.is_empty(), "Camera path has no keyframes");
+        let time = keyframes[0].time + time;
+
+        if time <= keyframes[0].time {
+            set_keyframe(camera, &keyframes[0]);
+            return;
+        }
+        //Index of the keyframe the segment containing time starts at.
+        let i = keyframes.partition_point(|k| k.time <= time) - 1;
+        if i + 1 == keyframes.len() {
+            set_keyframe(camera, &keyframes[i]);
+            return;
+        }
+
+        let k0 = &keyframes[i.saturating_sub(1)];
+        let k1 = &keyframes[i];
+        let k2 = &keyframes[i + 1];
+        let k3 = &keyframes[(i + 2).min(keyframes.len() - 1)];
+
+        let segment = k2.time - k1.time;
+        let t = (time - k1.time) / segment;
+
+        //Tangents are scaled by the time between keyframes, so unevenly spaced keyframes don't make the speed jump.
+        let m1 = scale_vector(&sub_vector(&k2.origin, &k0.origin), segment / (k2.time - k0.time));
+        let m2 = scale_vector(&sub_vector(&k3.origin, &k1.origin), segment / (k3.time - k1.time));
+
+        //Cubic Hermite basis.
+        let t2 = t * t;
+        let t3 = t2 * t;
+        let h00 = 2.0 * t3 - 3.0 * t2 + 1.0;
+        let h10 = t3 - 2.0 * t2 + t;
+        let h01 = -2.0 * t3 + 3.0 * t2;
+        let h11 = t3 - t2;
+
+        camera.origin = add_vector(
+            &add_vector(&scale_vector(&k1.origin, h00), &scale_vector(&m1, h10)),
+            &add_vector(&scale_vector(&k2.origin, h01), &scale_vector(&m2, h11)),
+        );
+        camera.fov_y = k1.fov_y + (k2.fov_y - k1.fov_y) * t;
+
+        let from = Quaternion::from_euler(k1.yaw, k1.pitch, k1.roll);
+        let to = Quaternion::from_euler(k2.yaw, k2.pitch, k2.roll);
+        camera.set_orientation(from.slerp(to, t));
+    }
+}
+
+fn set_keyframe(camera: &mut Camera, keyframe: &CameraKeyframe) {
+    camera.origin = keyframe.origin;
+    camera.yaw = keyframe.yaw;
+    camera.pitch = keyframe.pitch;
+    camera.roll = keyframe.roll;
+    camera.fov_y = keyframe.fov_y;
+    camera.update_basis();
+}
+
+//Samples the camera into a CameraPath while it is flown around.
+pub struct CameraRecorder {
+    pub path: CameraPath,
+    pub interval: f32, //Seconds between keyframes, the spline fills in the rest.
+    pub elapsed: f32,
+}
+
+impl Default for CameraRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CameraRecorder {
+    pub const DEFAULT_INTERVAL: f32 = 0.1;
+
+    pub fn new() -> Self {
+        Self {
+            path: CameraPath::default(),
+            interval: Self::DEFAULT_INTERVAL,
+            elapsed: 0.0,
+        }
+    }
+
+    pub fn update(&mut self, camera: &Camera, dt: Duration) {
+        if let Some(last) = self.path.keyframes.last() {
+            self.elapsed += dt.as_secs_f32();
+            if self.elapsed - last.time < self.interval {
+                return;
+            }
+        }
+        self.path.keyframes.push(CameraKeyframe::from_camera(camera, self.elapsed));
+    }
+
+    //Adds where the camera ended up, so the path doesn't stop up to interval seconds early.
+    pub fn finish(mut self, camera: &Camera) -> CameraPath {
+        if self.path.keyframes.last().is_some_and(|last| last.time < self.elapsed) {
+            self.path.keyframes.push(CameraKeyframe::from_camera(camera, self.elapsed));
+        }
+        self.path
+    }
+}
+
+//Plays a CameraPath back in real time, timing the frames so a playback doubles as a benchmark.
+pub struct CameraPlayer {
+    pub path: CameraPath,
+    pub time: f32,
+    pub frames: u32,
+    pub started: std::time::Instant,
+}
+
+impl CameraPlayer {
+    pub fn new(path: CameraPath) -> Self {
+        Self {
+            path,
+            time: 0.0,
+            frames: 0,
+            started: std::time::Instant::now(),
+        }
+    }
+
+    //Returns false once the end of the path has been shown.
+    pub fn update(&mut self, camera: &mut Camera, dt: Duration) -> bool {
+        if self.frames > 0 {
+            self.time += dt.as_secs_f32();
+        }
+        self.path.apply(self.time, camera);
+        self.frames += 1;
+        self.time < self.path.duration()
+    }
+}
@@ -1,12 +1,8 @@
-use noise::NoiseFn;
-use rand::Rng;
 use wgpu::util::DeviceExt;
 
-use crate::objects::{Object, Vertex};
+use crate::{objects::{Object, Vertex}, terrain::VoxelChunk};
 
-const CHUNK_SIZE: i32 = 32;
-
-const INDICES: [u16; 36] = [
+const INDICES: [u32; 36] = [
         //Top (+z)
         7, 4, 5,
         7, 5, 6,
@@ -33,25 +29,20 @@ const INDICES: [u16; 36] = [
 ];
 
 
-fn create_cube_mesh(x: f32,  y: f32, z:f32, index_offset:u16) -> ([Vertex; 8], [u16; 36]) {
-
-    // let color: [f32; 3] = [0.5, 0.5, 0.5];
-    let mut rng = rand::thread_rng();
-    
-    let color: [f32; 3] = [rng.gen_range(0..100) as f32 / 100f32, rng.gen_range(0..100) as f32 / 100f32, rng.gen_range(0..100) as f32 / 100f32];
+fn create_cube_mesh(x: f32,  y: f32, z:f32, index_offset:u32, color: [f32; 3]) -> ([Vertex; 8], [u32; 36]) {
 
     let vertices: [Vertex; 8] = [
-            Vertex{ position: [x, y, z], color: color}, //0
-            Vertex{ position: [x + 1.0, y, z], color: color}, //1
-            Vertex{ position: [x + 1.0, y + 1.0, z], color: color}, //2
-            Vertex{ position: [x, y + 1.0, z], color: color}, //3
-            Vertex{ position: [x, y, z + 1.0], color: color}, //4
-            Vertex{ position: [x + 1.0, y, z + 1.0], color: color}, //5
-            Vertex{ position: [x + 1.0, y + 1.0, z + 1.0], color: color}, //6
-            Vertex{ position: [x, y + 1.0, z + 1.0], color: color}, //7
+            Vertex{ position: [x, y, z], color}, //0
+            Vertex{ position: [x + 1.0, y, z], color}, //1
+            Vertex{ position: [x + 1.0, y + 1.0, z], color}, //2
+            Vertex{ position: [x, y + 1.0, z], color}, //3
+            Vertex{ position: [x, y, z + 1.0], color}, //4
+            Vertex{ position: [x + 1.0, y, z + 1.0], color}, //5
+            Vertex{ position: [x + 1.0, y + 1.0, z + 1.0], color}, //6
+            Vertex{ position: [x, y + 1.0, z + 1.0], color}, //7
         ];
 
-    let mut indices = INDICES.clone();
+    let mut indices = INDICES;
 
     for index in &mut indices {
         *index += index_offset;
@@ -60,53 +51,57 @@ fn create_cube_mesh(x: f32,  y: f32, z:f32, index_offset:u16) -> ([Vertex; 8], [
     (vertices, indices)
 }
 
-impl Object {
-    pub fn new_chunk(device: &wgpu::Device, chunk_x: i32, chunk_y: i32) -> Object {
-
-        let perlin = noise::Perlin::new(1);
+//The mesh of a chunk before it is uploaded, building it doesn't need the device so it can happen on any thread.
+pub struct ChunkMesh {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+}
 
-        let x_offset: i32 = chunk_x * CHUNK_SIZE;
-        let y_offset: i32 = chunk_y * CHUNK_SIZE;
+impl ChunkMesh {
+    //One cube per voxel of the chunk that touches air.
+    pub fn new(chunk: &VoxelChunk) -> Self {
         let mut current_offset_indices = 0;
-        //Todo probably change these to vectors when I want to create more proper terrain + cull faces that arent shown. 
-        let mut vertices: [Vertex; (8 * CHUNK_SIZE * CHUNK_SIZE) as usize] = [Vertex::default(); (8 * CHUNK_SIZE * CHUNK_SIZE) as usize];
-        let mut indices: [u16; (36 * CHUNK_SIZE * CHUNK_SIZE) as usize] = [0; (36 * CHUNK_SIZE * CHUNK_SIZE) as usize];
-
-        
-        for y in 0..CHUNK_SIZE {
-            for x in 0..CHUNK_SIZE {
-                // let z_val: f32 = 1.0;
-                let mut z_val = perlin.get([(x_offset + x) as f64 / 10.0, (y_offset + y) as f64 / 10.0]) * 4.0;
-                z_val = f64::floor(z_val as f64);
-                // println!("z_val: {:?}", z_val);
-                let (cube_vertex, cube_index) = create_cube_mesh((x_offset + x) as f32, (y_offset + y) as f32, z_val as f32, current_offset_indices);
-                
-                let v_index = ((CHUNK_SIZE * y + x) * 8) as usize;
-                vertices[v_index..v_index+8].copy_from_slice(&cube_vertex);
-                let i_index = ((CHUNK_SIZE * y + x) * 36) as usize;
-                indices[i_index..i_index+36].copy_from_slice(&cube_index);
-                current_offset_indices += 8;
-            }
+        //Todo cull faces that arent shown.
+        let mut vertices: Vec<Vertex> = vec![];
+        let mut indices: Vec<u32> = vec![];
+
+        for (position, voxel) in chunk.visible_voxels() {
+            let [r, g, b, _] = voxel.color_f32();
+            let (cube_vertex, cube_index) = create_cube_mesh(position[0] as f32, position[1] as f32, position[2] as f32, current_offset_indices, [r, g, b]);
+            vertices.extend_from_slice(&cube_vertex);
+            indices.extend_from_slice(&cube_index);
+            current_offset_indices += 8;
         }
 
+        Self {
+            vertices,
+            indices,
+        }
+    }
+}
 
+impl Object {
+    pub fn new_chunk(device: &wgpu::Device, chunk: &VoxelChunk) -> Object {
+        Self::from_mesh(device, &ChunkMesh::new(chunk))
+    }
 
+    pub fn from_mesh(device: &wgpu::Device, mesh: &ChunkMesh) -> Object {
         Self {
             vertex_buffer: device.create_buffer_init(
                 &wgpu::util::BufferInitDescriptor {
                     label: Some("Vertex Buffer"),
-                    contents: bytemuck::cast_slice(&vertices),
+                    contents: bytemuck::cast_slice(&mesh.vertices),
                     usage: wgpu::BufferUsages::VERTEX,
                 }
             ),
             index_buffer: device.create_buffer_init(
                 &wgpu::util::BufferInitDescriptor {
                     label: Some("Index Buffer"),
-                    contents: bytemuck::cast_slice(&indices),
+                    contents: bytemuck::cast_slice(&mesh.indices),
                     usage: wgpu::BufferUsages::INDEX,
                 }
             ),
-            num_vertices: indices.len() as u32
+            num_vertices: mesh.indices.len() as u32
         }
     }
 }
\ No newline at end of file
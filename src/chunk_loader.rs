@@ -0,0 +1,225 @@
+use std::{
+    collections::HashSet,
+    sync::{mpsc, Arc, Condvar, Mutex},
+    thread::JoinHandle,
+};
+
+use crate::{
+    chunk::ChunkMesh,
+    path_tracing::chunk::PTObject,
+    terrain::{TerrainGenerator, CHUNK_SIZE},
+};
+
+//A chunk with everything built that doesn't need the device, the main thread only has to upload it.
+pub struct LoadedChunk {
+    pub position: [i32; 2],
+    pub object: PTObject,
+    pub mesh: ChunkMesh,
+}
+
+impl LoadedChunk {
+    pub fn new(generator: &dyn TerrainGenerator, position: [i32; 2]) -> Self {
+        let chunk = generator.generate_chunk(position[0], position[1]);
+        Self {
+            position,
+            object: PTObject::new(&chunk),
+            mesh: ChunkMesh::new(&chunk),
+        }
+    }
+}
+
+struct LoadQueue {
+    pending: Vec<[i32; 2]>,
+    focus: [f32; 2], //In chunks, the pending chunk closest to it is built first.
+    stopping: bool,
+}
+
+//Generates chunks and builds their octrees and meshes on a pool of worker threads.
+//Finished chunks wait in a channel until the main thread picks them up with finished().
+pub struct ChunkLoader {
+    queue: Arc<(Mutex<LoadQueue>, Condvar)>,
+    finished: mpsc::Receiver<LoadedChunk>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl ChunkLoader {
+    //Leaves a core for the main thread, which keeps rendering while the chunks come in.
+    pub fn default_workers() -> usize {
+        std::thread::available_parallelism().map_or(1, |n| n.get().saturating_sub(1).max(1))
+    }
+
+    pub fn new(generator: Arc<dyn TerrainGenerator>, workers: usize) -> Self {
+        let queue = Arc::new((
+            Mutex::new(LoadQueue { pending: vec![], focus: [0.0; 2], stopping: false }),
+            Condvar::new(),
+        ));
+        let (sender, finished) = mpsc::channel();
+
+        let workers = (0..workers.max(1))
+            .map(|i| {
+                let queue = queue.clone();
+                let sender = sender.clone();
+                let generator = generator.clone();
+                std::thread::Builder::new()
+                    .name(format!("chunk worker {}", i))
+                    .spawn(move || {
+                        while let Some(position) = Self::next(&queue) {
+                            //Only fails when the loader is dropped, then nobody wants the chunk anymore.
+                            if sender.send(LoadedChunk::new(generator.as_ref(), position)).is_err() {
+                                break;
+                            }
+                        }
+                    })
+                    .unwrap()
+            })
+            .collect();
+
+        Self { queue, finished, workers }
+    }
+
+    //Blocks until there is a chunk to build, None once the loader is stopping.
+    fn next(queue: &(Mutex<LoadQueue>, Condvar)) -> Option<[i32; 2]> {
+        let (lock, condvar) = queue;
+        let mut queue = lock.lock().unwrap();
+        loop {
+            if queue.stopping {
+                return None;
+            }
+            let focus = queue.focus;
+            let distance = |position: &[i32; 2]| {
+                let dx = position[0] as f32 + 0.5 - focus[0];
+                let dy = position[1] as f32 + 0.5 - focus[1];
+                dx * dx + dy * dy
+            };
+            //The focus moves with the camera, so the closest chunk is looked up again every time instead of keeping a heap.
+            let closest = queue.pending.iter().enumerate()
+                .min_by(|a, b| distance(a.1).total_cmp(&distance(b.1)))
+                .map(|(i, _)| i);
+            if let Some(i) = closest {
+                return Some(queue.pending.swap_remove(i));
+            }
+            queue = condvar.wait(queue).unwrap();
+        }
+    }
+
+    //Queues chunks to be built, all at once so the workers can pick the closest among them.
+    pub fn request(&self, positions: impl IntoIterator<Item = [i32; 2]>) {
+        let (lock, condvar) = &*self.queue;
+        lock.lock().unwrap().pending.extend(positions);
+        condvar.notify_all();
+    }
+
+    //Drops the requests no worker has started on that keep says no to.
+    pub fn retain(&self, keep: impl FnMut(&[i32; 2]) -> bool) {
+        self.queue.0.lock().unwrap().pending.retain(keep);
+    }
+
+    //The world position chunks get built around, usually the camera's.
+    pub fn set_focus(&self, position: [f32; 3]) {
+        let (lock, _) = &*self.queue;
+        lock.lock().unwrap().focus = [position[0] / CHUNK_SIZE as f32, position[1] / CHUNK_SIZE as f32];
+    }
+
+    //Chunks that were requested but no worker has started on yet.
+    pub fn pending(&self) -> usize {
+        self.queue.0.lock().unwrap().pending.len()
+    }
+
+    //Chunks finished since the last call, without waiting for any others.
+    pub fn finished(&self) -> impl Iterator<Item = LoadedChunk> + '_ {
+        self.finished.try_iter()
+    }
+
+    //Waits for the next finished chunk, None if the workers are gone.
+    pub fn wait(&self) -> Option<LoadedChunk> {
+        self.finished.recv().ok()
+    }
+}
+
+impl Drop for ChunkLoader {
+    fn drop(&mut self) {
+        let (lock, condvar) = &*self.queue;
+        lock.lock().unwrap().stopping = true;
+        condvar.notify_all();
+        //A worker in the middle of a chunk finishes it first, its result is just thrown away.
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+//What the renderers have to change since the last ChunkStreamer::update.
+#[derive(Default)]
+pub struct StreamUpdate {
+    pub unloaded: Vec<[i32; 2]>,
+    pub loaded: Vec<LoadedChunk>,
+}
+
+//Keeps every chunk within render_distance of the focus loaded, along both x and y, and unloads the ones that fall out of it.
+pub struct ChunkStreamer {
+    pub render_distance: i32,
+    loader: ChunkLoader,
+    center: Option<[i32; 2]>, //Chunk the focus was in at the last update.
+    wanted: HashSet<[i32; 2]>, //In range, either loaded or still being built.
+    loaded: HashSet<[i32; 2]>,
+}
+
+impl ChunkStreamer {
+    pub fn new(loader: ChunkLoader, render_distance: i32) -> Self {
+        Self {
+            render_distance: render_distance.max(0),
+            loader,
+            center: None,
+            wanted: HashSet::new(),
+            loaded: HashSet::new(),
+        }
+    }
+
+    pub fn chunk_at(position: [f32; 3]) -> [i32; 2] {
+        [(position[0] / CHUNK_SIZE as f32).floor() as i32, (position[1] / CHUNK_SIZE as f32).floor() as i32]
+    }
+
+    pub fn is_loaded(&self, position: [i32; 2]) -> bool {
+        self.loaded.contains(&position)
+    }
+
+    //Chunks that are in range but haven't come in yet.
+    pub fn loading(&self) -> usize {
+        self.wanted.len() - self.loaded.len()
+    }
+
+    //Call every frame with the camera's position. Only when the camera crosses into another chunk do chunks get requested and unloaded,
+    //the chunks that finished in the meantime come in every call.
+    pub fn update(&mut self, focus: [f32; 3]) -> StreamUpdate {
+        self.loader.set_focus(focus);
+        let mut update = StreamUpdate::default();
+
+        let center = Self::chunk_at(focus);
+        if self.center != Some(center) {
+            self.center = Some(center);
+            let distance = self.render_distance;
+            let in_range = |position: &[i32; 2]| (position[0] - center[0]).abs() <= distance && (position[1] - center[1]).abs() <= distance;
+
+            self.loader.retain(in_range);
+            self.wanted.retain(in_range);
+            update.unloaded = self.loaded.iter().copied().filter(|position| !in_range(position)).collect();
+            self.loaded.retain(in_range);
+
+            let new: Vec<[i32; 2]> = (-distance..=distance)
+                .flat_map(|y| (-distance..=distance).map(move |x| [center[0] + x, center[1] + y]))
+                .filter(|position| !self.wanted.contains(position))
+                .collect();
+            self.wanted.extend(new.iter().copied());
+            self.loader.request(new);
+        }
+
+        //A chunk that went out of range while a worker was on it still comes in, it is thrown away here.
+        //If it came back into range it was requested again, then only the first of the two gets loaded.
+        for chunk in self.loader.finished() {
+            if self.wanted.contains(&chunk.position) && self.loaded.insert(chunk.position) {
+                update.loaded.push(chunk);
+            }
+        }
+        update
+    }
+}
@@ -1,6 +1,17 @@
 pub mod state;
 pub mod objects;
 pub mod camera;
+pub mod camera_path;
 pub mod texture;
 pub mod chunk;
-pub mod path_tracing;
\ No newline at end of file
+pub mod chunk_loader;
+pub mod terrain;
+pub mod path_tracing;
+pub mod offline_render;
+pub mod render_context;
+
+//Reads a config, pose or model file, a file that doesn't parse is InvalidData.
+pub fn load_ron<T: serde::de::DeserializeOwned>(path: impl AsRef<std::path::Path>) -> std::io::Result<T> {
+    let contents = std::fs::read_to_string(path)?;
+    ron::from_str(&contents).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
\ No newline at end of file
@@ -5,7 +5,7 @@ use winit::{
 use::ultimate_voxel_engine::*;
 
 
-async fn run() {
+async fn run(settings: state::WindowSettings) {
     // env_logger::init();
     let event_loop = EventLoop::new().unwrap();
     let window = WindowBuilder::new().build(&event_loop).unwrap();
@@ -18,7 +18,8 @@ async fn run() {
     }
     
 
-    let mut state = state::State::new(&window).await;
+    let mut state = state::State::new(&window, &settings).await;
+    let mut last_update = std::time::Instant::now();
 
     let _ = event_loop.run(move |event, control_flow| {
         match event {
@@ -32,7 +33,7 @@ async fn run() {
             Event::WindowEvent {
                 ref event,
                 window_id,
-            } if window_id == state.window().id() => if !state.input(event) {
+            } if window_id == state.window().id() && !state.input(event) => {
                 match event {
                     WindowEvent::CloseRequested
                     | WindowEvent::KeyboardInput {
@@ -49,7 +50,9 @@ async fn run() {
                     }
 
                     WindowEvent::RedrawRequested => {
-                        state.update();
+                        let now = std::time::Instant::now();
+                        state.update(now - last_update);
+                        last_update = now;
                         match state.render() {
                             Ok(_) => {},
                             Err(e) => eprintln!("{:?}", e),
@@ -72,5 +75,32 @@ async fn run() {
 
 fn main() {
     env_logger::init();
-    pollster::block_on(run());
+
+    let mut args = std::env::args().skip(1).peekable();
+    if args.peek().map(String::as_str) == Some("render") {
+        args.next();
+        let settings = match offline_render::RenderSettings::from_args(args) {
+            Ok(settings) => settings,
+            Err(e) => {
+                eprintln!("{}\n{}", e, offline_render::USAGE);
+                std::process::exit(2);
+            }
+        };
+
+        if let Err(e) = pollster::block_on(offline_render::render(&settings)) {
+            eprintln!("Render failed: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let settings = match state::WindowSettings::from_args(args) {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("{}\n{}\n{}", e, state::USAGE, offline_render::USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    pollster::block_on(run(settings));
 }
@@ -1,7 +1,8 @@
-use rand::Rng;
+use std::collections::HashMap;
+
 use wgpu::util::DeviceExt;
 
-use crate::{camera::Camera, texture};
+use crate::{camera::CameraBinding, chunk::ChunkMesh, terrain::hash_coords, texture};
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
@@ -32,7 +33,7 @@ impl Vertex {
     }
 }
 
-const INDICES: &[u16] = &[
+const INDICES: &[u32] = &[
         //Top (+z)
         7, 4, 5,
         7, 5, 6,
@@ -65,15 +66,12 @@ pub struct Object {
 }
 
 impl Object {
-    pub fn new_cube(device: &wgpu::Device) -> Object {
-        
-        let mut rng = rand::thread_rng();
-        //cube kek
-        let random_x: f32 = rng.gen_range(-15..15) as f32;
-        let random_y: f32 = rng.gen_range(-15..15) as f32;
-        let random_z: f32 = rng.gen_range(-15..15) as f32;
+    //A single cube at position, its color is hashed from the position and seed so it is the same every run.
+    pub fn new_cube(device: &wgpu::Device, position: [i32; 3], seed: u32) -> Object {
+        let [random_x, random_y, random_z] = position.map(|c| c as f32);
 
-        let random_color: [f32; 3] = [rng.gen_range(0..100) as f32 / 100f32, rng.gen_range(0..100) as f32 / 100f32, rng.gen_range(0..100) as f32 / 100f32];
+        let hash = hash_coords(seed, &position);
+        let random_color: [f32; 3] = [0, 1, 2].map(|i| ((hash >> (40 + 8 * i)) & 255) as f32 / 255.0);
 
         let vertices: &[Vertex] = &[
             Vertex{ position: [random_x, random_y, random_z], color: random_color}, //0
@@ -115,11 +113,17 @@ impl Object {
 //These objects can have their own meshes and will have seperated draw calls.
 pub struct ObjectGroup {
     pub render_pipeline: wgpu::RenderPipeline,
-    pub objects: Vec<Object>
+    pub objects: Vec<Object>,
+    pub chunks: HashMap<[i32; 2], Object>, //Streamed in and out around the camera, by chunk position.
 }
 
 impl ObjectGroup {
-    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, camera: &Camera) -> ObjectGroup {
+    //Starts out empty, the chunks are built by a ChunkLoader and added as they come in.
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, camera: &CameraBinding) -> ObjectGroup {
+        Self::from_objects(device, format, camera, vec![])
+    }
+
+    pub fn from_objects(device: &wgpu::Device, format: wgpu::TextureFormat, camera: &CameraBinding, objects: Vec<Object>) -> ObjectGroup {
 
         let shader = device.create_shader_module(wgpu::include_wgsl!("shader.wgsl"));
         let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -144,7 +148,7 @@ impl ObjectGroup {
             fragment: Some(wgpu::FragmentState { module: &shader, entry_point: "fs_main", 
                 compilation_options: wgpu::PipelineCompilationOptions::default(),
                 targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
+                    format,
                     blend: Some(wgpu::BlendState::REPLACE),
                     write_mask: wgpu::ColorWrites::ALL,
                 })]
@@ -169,24 +173,31 @@ impl ObjectGroup {
             multiview: None,
         });
 
-        let mut objects: Vec<Object> = Vec::new();
+        Self {
+            render_pipeline,
+            objects,
+            chunks: HashMap::new(),
+        }
+    }
 
-        // for _ in 0..1000 {
-        //     objects.push(Object::new_cube(device)); 
-        // }
-        objects.push(Object::new_chunk(device, 0, 0));
+    //Replaces the chunk if it was already there.
+    pub fn add_chunk(&mut self, device: &wgpu::Device, position: [i32; 2], mesh: &ChunkMesh) {
+        self.chunks.insert(position, Object::from_mesh(device, mesh));
+    }
 
-        for x in -5..5 {
-            for y in -5..5 {
-                objects.push(Object::new_chunk(device, x, y));
-            }
-            
-        }
+    pub fn remove_chunk(&mut self, position: [i32; 2]) -> bool {
+        self.chunks.remove(&position).is_some()
+    }
 
+    //Records the draw calls for every object into an already started render pass.
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, camera: &'a CameraBinding) {
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &camera.camera_bind_group, &[]);
 
-        Self {
-            render_pipeline,
-            objects
+        for object in self.objects.iter().chain(self.chunks.values()) {
+            render_pass.set_vertex_buffer(0, object.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(object.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+            render_pass.draw_indexed(0..object.num_vertices, 0, 0..1);
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,178 @@
+use std::{io, path::{Path, PathBuf}};
+
+use crate::{camera::{Camera, CameraPose}, camera_path::CameraPath, render_context::RenderContext, path_tracing::{pt_render::PTRender, render_image::RenderImage, scene::Scene}, terrain::{NoiseTerrain, TerrainConfig}};
+
+pub const USAGE: &str = "Usage: ultimate-voxel-engine render [--spp <samples>] [--supersample <n>] [--width <pixels>] [--height <pixels>] [--camera <file.ron>] [--camera-path <file.ron>] [--fps <n>] [--seed <n>] [--terrain <file.ron>] [--out <file.exr|file.pfm|file.png>] [--software]";
+
+//How many samples are submitted before waiting on the gpu, so the queue doesn't grow unbounded.
+const SAMPLES_PER_POLL: u32 = 16;
+
+pub struct RenderSettings {
+    pub samples_per_pixel: u32,
+    pub supersampling: u32, //Rays per pixel per sample is this squared, mostly for --spp 1 renders.
+    pub width: usize,
+    pub height: usize,
+    pub camera: Option<PathBuf>,
+    pub camera_path: Option<PathBuf>, //Renders a numbered image per frame along the path instead of a single image.
+    pub fps: f32,
+    pub seed: u32, //World seed of the terrain.
+    pub terrain: Option<PathBuf>, //TerrainConfig file, the default terrain without one.
+    pub out: PathBuf,
+    pub force_fallback_adapter: bool,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            samples_per_pixel: 64,
+            supersampling: 1,
+            width: 1920,
+            height: 1080,
+            camera: None,
+            camera_path: None,
+            fps: 30.0,
+            seed: NoiseTerrain::DEFAULT_SEED,
+            terrain: None,
+            out: PathBuf::from("render.exr"),
+            force_fallback_adapter: false,
+        }
+    }
+}
+
+impl RenderSettings {
+    //Parses the arguments that follow `render` on the command line.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut settings = Self::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("Missing value for {}", arg));
+            match arg.as_str() {
+                "--spp" => settings.samples_per_pixel = parse_number(&arg, value()?)?,
+                "--supersample" => settings.supersampling = parse_number(&arg, value()?)?,
+                "--width" => settings.width = parse_number(&arg, value()?)?,
+                "--height" => settings.height = parse_number(&arg, value()?)?,
+                "--camera" => settings.camera = Some(PathBuf::from(value()?)),
+                "--camera-path" => settings.camera_path = Some(PathBuf::from(value()?)),
+                "--fps" => settings.fps = parse_number(&arg, value()?)?,
+                "--seed" => settings.seed = parse_number(&arg, value()?)?,
+                "--terrain" => settings.terrain = Some(PathBuf::from(value()?)),
+                "--out" => settings.out = PathBuf::from(value()?),
+                "--software" => settings.force_fallback_adapter = true,
+                _ => return Err(format!("Unknown argument {}", arg)),
+            }
+        }
+
+        if settings.samples_per_pixel == 0 || settings.supersampling == 0 || settings.width < 2 || settings.height < 2 {
+            return Err("--spp and --supersample must be at least 1 and the image at least 2x2 pixels".to_string());
+        }
+
+        //RenderContext::headless asks for the default limits, larger images would fail validation on the gpu.
+        let max_pixels = PTRender::max_pixels(&wgpu::Limits::default());
+        if (settings.width * settings.height) as u64 > max_pixels {
+            return Err(format!("--width times --height can be at most {} pixels, {}x{} is too large", max_pixels, settings.width, settings.height));
+        }
+
+        if settings.fps <= 0.0 || !settings.fps.is_finite() {
+            return Err("--fps must be above 0".to_string());
+        }
+
+        if !matches!(settings.out.extension().and_then(|ext| ext.to_str()), Some("exr" | "pfm" | "png")) {
+            return Err("--out must end in .exr, .pfm or .png".to_string());
+        }
+
+        Ok(settings)
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(arg: &str, value: String) -> Result<T, String> {
+    value.parse().map_err(|_| format!("Invalid value for {}: {}", arg, value))
+}
+
+//Renders the scene without a window, accumulating samples_per_pixel samples before writing the image to settings.out.
+//With a camera path every frame gets written next to settings.out with its frame number appended, render_0000.exr and so on.
+pub async fn render(settings: &RenderSettings) -> io::Result<()> {
+    let context = RenderContext::headless(settings.force_fallback_adapter).await.map_err(io::Error::other)?;
+    let (device, queue) = (&context.device, &context.queue);
+    let screen_size = [settings.width, settings.height];
+
+    let terrain_config = match &settings.terrain {
+        Some(path) => TerrainConfig::load(path)?,
+        None => TerrainConfig::default(),
+    };
+    let scene = Scene::with_generator(&NoiseTerrain::new(settings.seed, terrain_config));
+    //The texture format only matters for presenting to a window, which we never do here.
+    let mut pt_render = PTRender::with_scene(device, queue, wgpu::TextureFormat::Rgba8UnormSrgb, screen_size, scene);
+
+    if let Some(camera_file) = &settings.camera {
+        let pose = CameraPose::load(camera_file)?;
+        pt_render.camera = Camera::from_pose(&pose, screen_size);
+        if pose.autofocus {
+            match pt_render.scene.autofocus(&pt_render.camera) {
+                Some(distance) => pt_render.camera.focus_distance = distance,
+                None => eprintln!("Autofocus found no voxel in the center of the screen, using focus_distance instead"),
+            }
+        }
+    }
+    pt_render.set_supersampling(queue, settings.supersampling);
+    if let Some(warning) = pt_render.software_limit_warning(&context) {
+        eprintln!("Warning: {}", warning);
+    }
+    //With a single sample the pixel centers give the cleanest image, jitter only pays off once samples get averaged.
+    pt_render.set_jitter(queue, settings.samples_per_pixel > 1);
+
+    let Some(camera_path) = &settings.camera_path else {
+        pt_render.update_camera_uniform(queue);
+        let image = render_frame(&mut pt_render, device, queue, settings, true);
+        save_image(&image, &settings.out)?;
+        println!("Saved render to {}", settings.out.display());
+        return Ok(());
+    };
+
+    //The path only moves and turns the camera, everything else still comes from --camera.
+    let camera_path = CameraPath::load(camera_path)?;
+    let frames = (camera_path.duration() * settings.fps).floor() as u32 + 1;
+    for frame in 0..frames {
+        camera_path.apply(frame as f32 / settings.fps, &mut pt_render.camera);
+        pt_render.update_camera_uniform(queue);
+
+        let image = render_frame(&mut pt_render, device, queue, settings, false);
+        let out = frame_path(&settings.out, frame);
+        save_image(&image, &out)?;
+        println!("Saved frame {}/{} to {}", frame + 1, frames, out.display());
+    }
+
+    Ok(())
+}
+
+fn render_frame(pt_render: &mut PTRender, device: &wgpu::Device, queue: &wgpu::Queue, settings: &RenderSettings, report_progress: bool) -> RenderImage {
+    for sample in 0..settings.samples_per_pixel {
+        pt_render.set_sample_index(queue, sample);
+        pt_render.dispatch_compute(device, queue);
+
+        if (sample + 1) % SAMPLES_PER_POLL == 0 {
+            device.poll(wgpu::Maintain::Wait);
+            if report_progress {
+                println!("Sample {}/{}", sample + 1, settings.samples_per_pixel);
+            }
+        }
+    }
+
+    pt_render.capture(device, queue)
+}
+
+//render.exr becomes render_0042.exr for frame 42.
+fn frame_path(out: &Path, frame: u32) -> PathBuf {
+    let stem = out.file_stem().and_then(|stem| stem.to_str()).unwrap_or("render");
+    let extension = out.extension().and_then(|ext| ext.to_str()).unwrap_or_default();
+    out.with_file_name(format!("{}_{:04}.{}", stem, frame, extension))
+}
+
+fn save_image(image: &RenderImage, out: &Path) -> io::Result<()> {
+    match out.extension().and_then(|ext| ext.to_str()) {
+        Some("exr") => image.save_exr(out),
+        Some("pfm") => image.save_pfm(out),
+        Some("png") => image.save_png(out),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "Output file must end in .exr, .pfm or .png")),
+    }
+}
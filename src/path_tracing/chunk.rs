@@ -1,16 +1,17 @@
-use std::{collections::VecDeque, u32};
+use std::collections::VecDeque;
 
-use noise::NoiseFn;
-use rand::Rng;
+use crate::terrain::{VoxelChunk, CHUNK_HEIGHT};
 
 use super::cube::Cube;
 
+pub use crate::terrain::CHUNK_SIZE;
+
 pub struct PTObject {
     pub cubes: Vec<Cube>,
-    pub octree: Option<SparseOctree>,
+    pub octrees: Vec<SparseOctree>, //CHUNK_SIZE cubes stacked from the bottom of the chunk up, empty ones left out.
 }
 
-#[derive(Debug, Copy, Clone,)]
+#[derive(Debug, Clone,)]
 pub struct SparseOctreeNode {
     pub is_leaf_node: bool,
     pub children: Option<Vec<SparseOctreeNode>>,
@@ -32,33 +33,20 @@ pub struct GpuOctNode {
     pub color: u32, //R8G8B8A8
 }
 
-const CHUNK_SIZE: i32 = 64;
-
-fn cube_at_loc(cubes: &Vec<Cube>, loc: [i32; 3]) -> bool {
-    //todo! Construct a lookup table at chunk generation instead of a vec of cubes.
-    
-    for cube in cubes {
-        if cube.min[0] as i32 == loc[0] &&
-           cube.min[1] as i32 == loc[1] &&
-           cube.min[2] as i32 == loc[2] {
-            return true;
-        }
-    }
-
-
-    return false;
-}
-
-fn construct_child(cubes: &Vec<Cube>, bounds: [[i32; 3]; 2]) -> Option<SparseOctreeNode> {
+//Bounds are in the local coordinates of the chunk.
+fn construct_child(chunk: &VoxelChunk, bounds: [[i32; 3]; 2]) -> Option<SparseOctreeNode> {
 
-    if (bounds[1][0] - bounds[0][0]) as i32 == 1 {
-        if cube_at_loc(cubes, bounds[0]){
+    if (bounds[1][0] - bounds[0][0]) == 1 {
+        //Only voxels touching air get a leaf, like the cubes. Rays always hit those first,
+        //so the solid rock between the surface and the caves would only make the tree bigger.
+        if chunk.is_visible(bounds[0]) {
+            let voxel = chunk.get(bounds[0]);
             // println!("Spawning leaf node!");
             Some(SparseOctreeNode {
                 is_leaf_node: true,
                 children: None,
                 child_mask: None,
-                color: Some([0.0, 1.0, 0.0, 0.0]),
+                color: Some(voxel.color_f32()),
             })
         } else {
             None
@@ -108,14 +96,11 @@ fn construct_child(cubes: &Vec<Cube>, bounds: [[i32; 3]; 2]) -> Option<SparseOct
 
 
 
-                    let child = construct_child(cubes, [child_bounds_aa, child_bounds_bb]);
-                    match child {
-                        Some(node) => {
-                            children.push(node);
-                            let child_nr = z * 4 + y * 2 + x;
-                            child_mask |= 1 << child_nr;
-                        },
-                        None => {},
+                    let child = construct_child(chunk, [child_bounds_aa, child_bounds_bb]);
+                    if let Some(node) = child {
+                        children.push(node);
+                        let child_nr = z * 4 + y * 2 + x;
+                        child_mask |= 1 << child_nr;
                     } 
                 }
             }
@@ -137,104 +122,88 @@ fn construct_child(cubes: &Vec<Cube>, bounds: [[i32; 3]; 2]) -> Option<SparseOct
     }
 }
 
-fn construct_octree(cubes: &Vec<Cube>, bounds: [[i32; 3]; 2]) -> Option<SparseOctree> {
-    let root_node = construct_child(cubes, bounds);
-    match root_node {
-        Some(tree) => {
-            Some(SparseOctree {
-                aabb: bounds,
-                max_depth: 14,
+//The chunk is taller than it is wide, an octree has to be a cube, so it gets one tree per CHUNK_SIZE of height.
+fn construct_octrees(chunk: &VoxelChunk) -> Vec<SparseOctree> {
+    let origin = chunk.origin();
+    (0..CHUNK_HEIGHT / CHUNK_SIZE)
+        .filter_map(|i| {
+            let bottom = i * CHUNK_SIZE;
+            let root_node = construct_child(chunk, [[0, 0, bottom], [CHUNK_SIZE, CHUNK_SIZE, bottom + CHUNK_SIZE]]);
+            root_node.map(|tree| SparseOctree {
+                aabb: [
+                    [origin[0], origin[1], origin[2] + bottom],
+                    [origin[0] + CHUNK_SIZE, origin[1] + CHUNK_SIZE, origin[2] + bottom + CHUNK_SIZE],
+                ],
+                max_depth: CHUNK_SIZE.ilog2(),
                 root: tree,
             })
-        }
-        None => None
-    }
+        })
+        .collect()
 }
 
 
 impl PTObject {
-    pub fn new(chunk_x: i32, chunk_y: i32) -> Self {
-        let perlin = noise::Perlin::new(1);
-
-        let x_offset: i32 = chunk_x * CHUNK_SIZE;
-        let y_offset: i32 = chunk_y * CHUNK_SIZE;
-
-        let mut cubes: Vec<Cube> = vec![];
-
-        let mut rng = rand::thread_rng();
-    
-        for y in 0..CHUNK_SIZE {
-            for x in 0..CHUNK_SIZE {
-                // let z_val: f32 = 1.0;
-                let mut z_val = perlin.get([(x_offset + x) as f64 / 10.0, (y_offset + y) as f64 / 10.0]) * 4.0;
-                z_val = f64::abs(f64::floor(z_val as f64));
-                let color: [f32; 4] = [rng.gen_range(0..100) as f32 / 100f32, rng.gen_range(0..100) as f32 / 100f32, rng.gen_range(0..100) as f32 / 100f32, 1.0];
-                let cube = Cube::new_cube_at(&[(x_offset + x) as f32, (y_offset + y) as f32, z_val as f32], color);
-                // println!("z_val: {:?}", z_val);
-                cubes.push(cube);
-            }
-        }
-        
-        let bounds = [
-            [x_offset, y_offset, 0],
-            [x_offset + CHUNK_SIZE, y_offset + CHUNK_SIZE, CHUNK_SIZE ]
-        ];
+    //One cube per voxel of the chunk that touches air, the octree has all of them.
+    pub fn new(chunk: &VoxelChunk) -> Self {
+        let cubes: Vec<Cube> = chunk.visible_voxels()
+            .map(|(position, voxel)| Cube::new_cube_at(&position.map(|c| c as f32), voxel.color_f32()))
+            .collect();
 
-        let octree = construct_octree(&cubes, bounds);
+        let octrees = construct_octrees(chunk);
 
         Self {
-            cubes: cubes,
-            octree: octree,
+            cubes,
+            octrees,
         }
 
     }
 
     pub fn get_cubes(&self) -> &Vec<Cube> {
-        return &self.cubes;
+        &self.cubes
     }
 
+    //The trees are laid out one after the other, starting_index is where the children of the first root go.
     pub fn get_octree_array(&self, starting_index: &mut u32) -> Vec<GpuOctNode> {
         let mut octree_vec: Vec<GpuOctNode> = vec![];
 
-        match &self.octree  {
-            Some(octree) => {
-                let mut octree_queue: VecDeque<&SparseOctreeNode> = VecDeque::new();
-                octree_queue.push_back(&octree.root);
-                
-                while octree_queue.len() > 0 {
-                    let current_node = octree_queue.pop_front().unwrap();
-                    let children_count = amount_of_children(current_node);
-                    if children_count > 0 {
-                        let children_vec = current_node.children.as_ref().unwrap();
-                        for i in 0..children_count {
-                            octree_queue.push_back(&children_vec[i as usize]);
-                        }
-
-
-                        octree_vec.push(GpuOctNode {
-                            child_index: starting_index.clone(),
-                            child_mask: current_node.child_mask.as_ref().unwrap().clone(),
-                            color: 0,
-                        });
-                        *starting_index += children_count;
-
-                    } else {
-                        octree_vec.push(GpuOctNode {
-                            child_index: 0,
-                            child_mask: 0,
-                            color: u32::MAX, // White cube for now, fix this later.
-                        });
+        for (i, octree) in self.octrees.iter().enumerate() {
+            //The previous tree ends where this root goes, so its children start right after it.
+            if i > 0 {
+                *starting_index += 1;
+            }
+
+            let mut octree_queue: VecDeque<&SparseOctreeNode> = VecDeque::new();
+            octree_queue.push_back(&octree.root);
+
+            while !octree_queue.is_empty() {
+                let current_node = octree_queue.pop_front().unwrap();
+                let children_count = amount_of_children(current_node);
+                if children_count > 0 {
+                    let children_vec = current_node.children.as_ref().unwrap();
+                    for i in 0..children_count {
+                        octree_queue.push_back(&children_vec[i as usize]);
                     }
-                    
-                }
 
-                
 
+                    octree_vec.push(GpuOctNode {
+                        child_index: *starting_index,
+                        child_mask: *current_node.child_mask.as_ref().unwrap(),
+                        color: 0,
+                    });
+                    *starting_index += children_count;
+
+                } else {
+                    octree_vec.push(GpuOctNode {
+                        child_index: 0,
+                        child_mask: 0,
+                        color: u32::MAX, // White cube for now, fix this later.
+                    });
+                }
 
-                octree_vec
-            },
-            _ => octree_vec
+            }
         }
+
+        octree_vec
     }
 }
 
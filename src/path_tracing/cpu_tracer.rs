@@ -0,0 +1,265 @@
+use crate::camera::Projection;
+
+use super::{cube::Cube, debug_view::{DebugUniform, DebugView}, pt_render::ComputeParams, ray::Ray, render_image::RenderImage, tracing_camera::TracingCameraUniform, vector_funcs::{add_vector, scale_vector, sub_vector, vector_length}};
+
+//Cpu version of path_tracer.wgsl, used as a reference on machines without a gpu.
+//Every function mirrors the shader function with the same name and does its math in the same order,
+//so any change to the shader has to be made here as well.
+pub struct CpuTracer<'a> {
+    pub params: ComputeParams,
+    pub camera: TracingCameraUniform,
+    pub cubes: &'a [Cube],
+    pub debug_view: DebugUniform,
+}
+
+const MAXFLOAT: f32 = f32::MAX;
+const PI: f32 = std::f32::consts::PI;
+
+impl CpuTracer<'_> {
+    pub fn main(&self, x: u32, y: u32) -> [f32; 4] {
+        let index = x + self.params.screen_width * y;
+
+        let offset = self.pixel_offset(index);
+        let n = self.params.supersampling;
+        let mut color = [0.0; 4];
+        for sy in 0..n {
+            for sx in 0..n {
+                let sub_pixel = [(sx as f32 + offset[0]) / n as f32, (sy as f32 + offset[1]) / n as f32];
+                let seed = index ^ hash_u32(self.params.sample_index.wrapping_mul(n * n).wrapping_add(sy * n + sx));
+                let sample = self.trace_sample([x as f32 + sub_pixel[0], y as f32 + sub_pixel[1]], seed);
+                for (c, s) in color.iter_mut().zip(sample) {
+                    *c += s;
+                }
+            }
+        }
+        color.map(|c| c / (n * n) as f32)
+    }
+
+    fn pixel_offset(&self, index: u32) -> [f32; 2] {
+        if self.params.jitter == 0 {
+            return [0.5, 0.5];
+        }
+        let sample = self.params.sample_index.wrapping_add(1);
+        let point = [halton(sample, 2), halton(sample, 3)];
+        let seed = hash_u32(index ^ 0x9e3779b9);
+        let shifted = [point[0] + random_float(seed), point[1] + random_float(hash_u32(seed))];
+        [shifted[0] - shifted[0].floor(), shifted[1] - shifted[1].floor()]
+    }
+
+    fn trace_sample(&self, pixel: [f32; 2], seed: u32) -> [f32; 4] {
+        let origin = xyz(&self.camera.origin);
+        let forward_vec = xyz(&self.camera.forward_vec);
+        let left_vec = xyz(&self.camera.left_vec);
+        let up_vec = xyz(&self.camera.up_vec);
+
+        let plane_center = add_vector(&origin, &forward_vec);
+        let half_height = self.camera.tan_half_fov_y;
+        let half_width = half_height * self.camera.aspect_ratio;
+
+        let bottom_left = sub_vector(&add_vector(&plane_center, &scale_vector(&left_vec, half_width)), &scale_vector(&up_vec, half_height));
+
+        let v = pixel[1] / self.params.screen_height as f32;
+        let u = pixel[0] / self.params.screen_width as f32;
+
+        let screen_place = add_vector(
+            &sub_vector(&bottom_left, &scale_vector(&scale_vector(&scale_vector(&left_vec, u), 2.0), half_width)),
+            &scale_vector(&scale_vector(&scale_vector(&up_vec, v), 2.0), half_height),
+        );
+
+        let mut ray_origin = origin;
+        let mut velocity = sub_vector(&screen_place, &origin);
+
+        match Projection::from_u32(self.camera.projection) {
+            Projection::Orthographic => {
+                let ortho_half_height = self.camera.ortho_height * 0.5;
+                let ortho_half_width = ortho_half_height * self.camera.aspect_ratio;
+                ray_origin = add_vector(
+                    &add_vector(&origin, &scale_vector(&left_vec, ortho_half_width * (1.0 - 2.0 * u))),
+                    &scale_vector(&up_vec, ortho_half_height * (2.0 * v - 1.0)),
+                );
+                velocity = forward_vec;
+            }
+            Projection::Equirectangular => {
+                let longitude = (u - 0.5) * 2.0 * PI;
+                let latitude = (v - 0.5) * PI;
+                velocity = add_vector(
+                    &scale_vector(
+                        &sub_vector(&scale_vector(&forward_vec, longitude.cos()), &scale_vector(&left_vec, longitude.sin())),
+                        latitude.cos(),
+                    ),
+                    &scale_vector(&up_vec, latitude.sin()),
+                );
+            }
+            Projection::Perspective => {
+                if self.camera.aperture_radius > 0.0 {
+                    let radius = self.camera.aperture_radius * random_float(seed).sqrt();
+                    let angle = 2.0 * PI * random_float(hash_u32(seed));
+                    let focus_point = add_vector(&origin, &scale_vector(&velocity, self.camera.focus_distance));
+                    ray_origin = add_vector(
+                        &add_vector(&origin, &scale_vector(&left_vec, radius * angle.cos())),
+                        &scale_vector(&up_vec, radius * angle.sin()),
+                    );
+                    velocity = sub_vector(&focus_point, &ray_origin);
+                }
+            }
+        }
+
+        let mut ray = Ray {
+            origin: ray_origin,
+            velocity,
+            distance: MAXFLOAT,
+            color: self.params.background_rgba,
+            hit_index: -1,
+            boxes_hit: 0,
+        };
+
+        for i in 0..self.params.amount_of_cubes as i32 {
+            self.cubes[i as usize].intersect_ray(i, &mut ray);
+        }
+
+        if self.debug_view.mode != DebugView::Off as u32 {
+            return self.debug_color(&ray);
+        }
+
+        ray.color
+    }
+
+    fn debug_color(&self, ray: &Ray) -> [f32; 4] {
+        let hit = ray.hit_index >= 0;
+        let point = add_vector(&ray.origin, &scale_vector(&ray.velocity, ray.distance));
+        let black = [0.0, 0.0, 0.0, 1.0];
+
+        match self.debug_view.view() {
+            DebugView::Normals => {
+                if !hit {
+                    return black;
+                }
+                let normal = self.cubes[ray.hit_index as usize].normal(point);
+                [normal[0] * 0.5 + 0.5, normal[1] * 0.5 + 0.5, normal[2] * 0.5 + 0.5, 1.0]
+            }
+            DebugView::Depth => {
+                if !hit {
+                    return black;
+                }
+                let depth = 1.0 - (ray.distance * vector_length(&ray.velocity) / self.debug_view.max_depth).clamp(0.0, 1.0);
+                [depth, depth, depth, 1.0]
+            }
+            DebugView::VoxelId => {
+                if !hit {
+                    return black;
+                }
+                let h = hash_u32(ray.hit_index as u32);
+                [
+                    (h & 255) as f32 / 255.0,
+                    ((h >> 8) & 255) as f32 / 255.0,
+                    ((h >> 16) & 255) as f32 / 255.0,
+                    1.0,
+                ]
+            }
+            DebugView::BoxesHit => {
+                let heat = heatmap(ray.boxes_hit as f32 / self.debug_view.heatmap_max_boxes as f32);
+                [heat[0], heat[1], heat[2], 1.0]
+            }
+            DebugView::ChunkBounds => {
+                if !hit {
+                    return ray.color;
+                }
+                let chunk_size = self.debug_view.chunk_size;
+                let line_width = 0.1;
+                let near_bound = point[0..2].iter().any(|p| {
+                    let in_chunk = p - (p / chunk_size).floor() * chunk_size;
+                    in_chunk < line_width || in_chunk > chunk_size - line_width
+                });
+                if near_bound {
+                    return [1.0, 0.0, 0.0, 1.0];
+                }
+                ray.color
+            }
+            DebugView::Off => ray.color,
+        }
+    }
+
+    //Traces one sample for every pixel, averaging it into output like the shader does with screen_pixels.
+    //Rows are split evenly over all available threads.
+    pub fn render_sample(&self, output: &mut RenderImage) {
+        let width = self.params.screen_width as usize;
+        let height = self.params.screen_height as usize;
+        assert_eq!(output.pixels.len(), width * height, "Output image does not match the screen size");
+        if output.pixels.is_empty() {
+            return;
+        }
+
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let rows_per_thread = height.div_ceil(threads);
+
+        std::thread::scope(|scope| {
+            for (chunk_index, rows) in output.pixels.chunks_mut(rows_per_thread * width).enumerate() {
+                scope.spawn(move || {
+                    for (i, pixel) in rows.iter_mut().enumerate() {
+                        let index = chunk_index * rows_per_thread * width + i;
+                        let color = self.main((index % width) as u32, (index / width) as u32);
+
+                        *pixel = if self.params.sample_index > 0 {
+                            mix(pixel, &color, 1.0 / (self.params.sample_index + 1) as f32)
+                        } else {
+                            color
+                        };
+                    }
+                });
+            }
+        });
+    }
+
+    pub fn render(&self) -> RenderImage {
+        let mut render_image = RenderImage::new(self.params.screen_width as usize, self.params.screen_height as usize);
+        self.render_sample(&mut render_image);
+        render_image
+    }
+}
+
+fn xyz(v: &[f32; 4]) -> [f32; 3] {
+    [v[0], v[1], v[2]]
+}
+
+fn mix(a: &[f32; 4], b: &[f32; 4], t: f32) -> [f32; 4] {
+    let mut result = [0.0; 4];
+    for (i, value) in result.iter_mut().enumerate() {
+        *value = a[i] * (1.0 - t) + b[i] * t;
+    }
+    result
+}
+
+fn heatmap(t: f32) -> [f32; 3] {
+    let x = t.clamp(0.0, 1.0);
+    [
+        (1.5 - (4.0 * x - 3.0).abs()).clamp(0.0, 1.0),
+        (1.5 - (4.0 * x - 2.0).abs()).clamp(0.0, 1.0),
+        (1.5 - (4.0 * x - 1.0).abs()).clamp(0.0, 1.0),
+    ]
+}
+
+fn hash_u32(x: u32) -> u32 {
+    let mut h = x;
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x7feb352d);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x846ca68b);
+    h ^= h >> 16;
+    h
+}
+
+fn random_float(seed: u32) -> f32 {
+    (hash_u32(seed) >> 8) as f32 / 16777216.0
+}
+
+fn halton(i: u32, base: u32) -> f32 {
+    let mut f = 1.0;
+    let mut result = 0.0;
+    let mut n = i;
+    while n > 0 {
+        f /= base as f32;
+        result += f * (n % base) as f32;
+        n /= base;
+    }
+    result
+}
@@ -14,42 +14,68 @@ impl Cube {
         Self {
             min: [loc[0], loc[1], loc[2], 0.0],
             max: [loc[0] + 1.0, loc[1] + 1.0, loc[2] + 1.0, 0.0],
-            color: color,
+            color,
         }
     }
 
-    pub fn intersect_ray(&self, ray: &mut Ray){
-        //https://tavianator.com/cgit/dimension.git/tree/libdimension/bvh/bvh.c#n196
-        //https://education.siggraph.org/static/HyperGraph/raytrace/rtinter3.htm
-        //Not the best way yet, but more intuitive.
-        let mut tnear = f32::MIN;
-        let mut tfar = f32::MAX;
-
-        for d in 0..3 as usize {
-
-            if ray.velocity[d] == 0.0 {
-                if ray.origin[d] >= self.min[d] && ray.origin[d] <= self.max[d]{
-                    continue
-                } else {
-                    return;
-                }
-            }
+    //Mirrors intersect_ray in path_tracer.wgsl, keep the two in sync.
+    pub fn intersect_ray(&self, index: i32, ray: &mut Ray){
+        let inv_velocity = [1.0 / ray.velocity[0], 1.0 / ray.velocity[1], 1.0 / ray.velocity[2]];
+
+        let tx1 = (self.min[0] - ray.origin[0]) * inv_velocity[0];
+        let tx2 = (self.max[0] - ray.origin[0]) * inv_velocity[0];
+
+        let mut tmin = tx1.min(tx2);
+        let mut tmax = tx1.max(tx2);
+
+        let ty1 = (self.min[1] - ray.origin[1]) * inv_velocity[1];
+        let ty2 = (self.max[1] - ray.origin[1]) * inv_velocity[1];
 
-            let t1 = (self.min[d] - ray.origin[d]) / ray.velocity[d];
-            let t2 = (self.max[d] - ray.origin[d]) / ray.velocity[d];
-            let tmin = t1.min(t2);
-            let tmax = t1.max(t2);
+        tmin = tmin.max(ty1.min(ty2));
+        tmax = tmax.min(ty1.max(ty2));
 
-            tnear = tnear.max(tmin);
-            tfar = tfar.min(tmax);
+        let tz1 = (self.min[2] - ray.origin[2]) * inv_velocity[2];
+        let tz2 = (self.max[2] - ray.origin[2]) * inv_velocity[2];
+
+        tmin = tmin.max(tz1.min(tz2));
+        tmax = tmax.min(tz1.max(tz2));
+
+        if tmax >= tmin.max(0.0) {
+            ray.boxes_hit += 1;
+
+            if tmin < ray.distance {
+                ray.color = self.color;
+                ray.distance = tmin;
+                ray.hit_index = index;
+            }
         }
+    }
 
-        if tnear > tfar || tfar < 0.0 {
-            return;
-        } else {
-            ray.distance = tnear;
-            ray.color = self.color;
+    //Mirrors cube_normal in path_tracer.wgsl.
+    pub fn normal(&self, point: [f32; 3]) -> [f32; 3] {
+        let mut d = [0.0; 3];
+        for i in 0..3 {
+            d[i] = (point[i] - (self.min[i] + self.max[i]) * 0.5) / ((self.max[i] - self.min[i]) * 0.5);
         }
+        let a = [d[0].abs(), d[1].abs(), d[2].abs()];
 
+        if a[0] > a[1] && a[0] > a[2] {
+            return [wgsl_sign(d[0]), 0.0, 0.0];
+        }
+        if a[1] > a[2] {
+            return [0.0, wgsl_sign(d[1]), 0.0];
+        }
+        [0.0, 0.0, wgsl_sign(d[2])]
     }
-}
\ No newline at end of file
+}
+
+//wgsl's sign() returns 0.0 for 0.0, unlike f32::signum.
+fn wgsl_sign(value: f32) -> f32 {
+    if value > 0.0 {
+        1.0
+    } else if value < 0.0 {
+        -1.0
+    } else {
+        0.0
+    }
+}
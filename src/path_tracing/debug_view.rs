@@ -0,0 +1,55 @@
+//Debug visualizations the path tracer can output instead of the regular image.
+//The discriminants are uploaded to the shader as-is, so keep them in sync with path_tracer.wgsl.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DebugView {
+    #[default]
+    Off = 0,
+    Normals = 1,
+    Depth = 2,
+    VoxelId = 3,
+    BoxesHit = 4,
+    ChunkBounds = 5,
+}
+
+impl DebugView {
+    pub const ALL: [DebugView; 6] = [
+        DebugView::Off,
+        DebugView::Normals,
+        DebugView::Depth,
+        DebugView::VoxelId,
+        DebugView::BoxesHit,
+        DebugView::ChunkBounds,
+    ];
+
+    pub fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct DebugUniform {
+    pub mode: u32,
+    pub heatmap_max_boxes: u32, //Amount of boxes hit that maps to the hottest color in the heatmap.
+    pub max_depth: f32, //Distance that maps to black in the depth view.
+    pub chunk_size: f32,
+}
+
+impl DebugUniform {
+    pub fn new(view: DebugView, chunk_size: f32) -> Self {
+        Self {
+            mode: view as u32,
+            heatmap_max_boxes: 32,
+            max_depth: 128.0,
+            chunk_size,
+        }
+    }
+
+    pub fn view(&self) -> DebugView {
+        DebugView::ALL
+            .into_iter()
+            .find(|view| *view as u32 == self.mode)
+            .unwrap_or_default()
+    }
+}
@@ -6,4 +6,6 @@ pub mod ray;
 pub mod vector_funcs;
 pub mod tracing_camera;
 pub mod scene;
-pub mod chunk;
\ No newline at end of file
+pub mod chunk;
+pub mod debug_view;
+pub mod cpu_tracer;
\ No newline at end of file
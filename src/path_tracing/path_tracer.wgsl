@@ -9,6 +9,13 @@ struct Camera {
     forward_vec: vec3<f32>,
     left_vec: vec3<f32>,
     up_vec: vec3<f32>,
+    _padding: f32, //Keeps the layout equal to TracingCameraUniform, @align is not respected here.
+    tan_half_fov_y: f32,
+    aspect_ratio: f32,
+    aperture_radius: f32,
+    focus_distance: f32,
+    projection: u32,
+    ortho_height: f32,
 }
 
 struct Ray {
@@ -16,17 +23,51 @@ struct Ray {
     velocity: vec3<f32>,
     distance: f32,
     color: vec4<f32>,
+    hit_index: i32,
+    boxes_hit: u32,
 }
 
-@group(0) @binding(0) var<uniform> amount_of_cubes: f32;
+struct Params {
+    amount_of_cubes: f32,
+    screen_width: u32,
+    screen_height: u32,
+    sample_index: u32, //How many samples are already accumulated in screen_pixels.
+    supersampling: u32,
+    jitter: u32,
+    background_rgba: vec4<f32>,
+}
+
+struct DebugView {
+    mode: u32,
+    heatmap_max_boxes: u32,
+    max_depth: f32,
+    chunk_size: f32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
 @group(0) @binding(1) var<uniform> camera: Camera;
 @group(0) @binding(2) var<storage, read> cubes: array<Cube>;
 @group(0) @binding(3) var<storage, read_write> screen_pixels: array<vec4<f32>>;
+@group(0) @binding(4) var<uniform> debug_view: DebugView;
+
+//Keep in sync with DebugView in debug_view.rs
+const DEBUG_OFF = 0u;
+const DEBUG_NORMALS = 1u;
+const DEBUG_DEPTH = 2u;
+const DEBUG_VOXEL_ID = 3u;
+const DEBUG_BOXES_HIT = 4u;
+const DEBUG_CHUNK_BOUNDS = 5u;
+
+//Keep in sync with Projection in camera.rs
+const PROJECTION_PERSPECTIVE = 0u;
+const PROJECTION_ORTHOGRAPHIC = 1u;
+const PROJECTION_EQUIRECTANGULAR = 2u;
 
 const maxfloat = 0x1.fffffep+127f;
 const minfloat = -0x1.fffffep+127f;
+const PI = 3.14159265;
 
-fn intersect_ray(cube: Cube, ray: Ray) -> Ray {
+fn intersect_ray(cube: Cube, index: i32, ray: Ray) -> Ray {
     //Branchless AABB testing right now, we want to change this to use DDA with a Spare Octree instead.
     //This should help speedup the code and not having to store the aabb should hopefully help reduce memory as well.
     //We would need a way to find out the collission points and then figure the normal out from there.
@@ -62,49 +103,224 @@ fn intersect_ray(cube: Cube, ray: Ray) -> Ray {
     tmin = max(tmin, min(tz1, tz2));
     tmax = min(tmax, max(tz1, tz2));
     
-    if (tmax >= max(0.0, tmin) && tmin < ray.distance) {
-        new_ray.color = cube.color;
-        new_ray.distance = tmin;
+    if (tmax >= max(0.0, tmin)) {
+        //Counts every box the ray passes through, not just the closest. There is no octree yet, so this is the brute force loop over every cube.
+        new_ray.boxes_hit += 1u;
+
+        if (tmin < ray.distance) {
+            new_ray.color = cube.color;
+            new_ray.distance = tmin;
+            new_ray.hit_index = index;
+        }
     }
 
     return new_ray;
 }
 
-@compute
-@workgroup_size(64)
-fn main(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
-    //Todo! Fix FOV
-    let total = arrayLength(&screen_pixels);
-    let index = global_invocation_id.x + 1920 * global_invocation_id.y;
+//The normal of the face the point lies on, found by checking which axis the point is furthest along from the center.
+fn cube_normal(cube: Cube, point: vec3<f32>) -> vec3<f32> {
+    let d = (point - (cube.min + cube.max) * 0.5) / ((cube.max - cube.min) * 0.5);
+    let a = abs(d);
 
-    if (index >= total) {
-        return;
+    if (a.x > a.y && a.x > a.z) {
+        return vec3<f32>(sign(d.x), 0.0, 0.0);
+    }
+    if (a.y > a.z) {
+        return vec3<f32>(0.0, sign(d.y), 0.0);
     }
+    return vec3<f32>(0.0, 0.0, sign(d.z));
+}
 
-    let plane_center = camera.origin + camera.forward_vec * 3.0;
-    let aspect_ratio = 16.0 / 9.0;
+//Blue -> green -> red color ramp, t is expected to be in [0, 1].
+fn heatmap(t: f32) -> vec3<f32> {
+    let x = clamp(t, 0.0, 1.0);
+    return clamp(
+        vec3<f32>(1.5 - abs(4.0 * x - 3.0), 1.5 - abs(4.0 * x - 2.0), 1.5 - abs(4.0 * x - 1.0)),
+        vec3<f32>(0.0),
+        vec3<f32>(1.0),
+    );
+}
 
-    let top_left = plane_center + aspect_ratio * camera.left_vec + camera.up_vec;
+fn hash_u32(x: u32) -> u32 {
+    var h = x;
+    h ^= h >> 16u;
+    h *= 0x7feb352du;
+    h ^= h >> 15u;
+    h *= 0x846ca68bu;
+    h ^= h >> 16u;
+    return h;
+}
 
-    let v = f32(global_invocation_id.y) / 1079.0;
-    let u = f32(global_invocation_id.x) / 1919.0;
+//Uniform in [0, 1), only uses 24 bits so the conversion to f32 is exact.
+fn random_float(seed: u32) -> f32 {
+    return f32(hash_u32(seed) >> 8u) / 16777216.0;
+}
 
-    let screen_place = top_left - camera.left_vec * u * 2.0 * aspect_ratio - camera.up_vec * v * 2.0;
+//Radical inverse of i, the Halton sequence spreads consecutive samples evenly over [0, 1).
+fn halton(i: u32, base: u32) -> f32 {
+    var f = 1.0;
+    var result = 0.0;
+    var n = i;
+    while (n > 0u) {
+        f = f / f32(base);
+        result = result + f * f32(n % base);
+        n = n / base;
+    }
+    return result;
+}
 
-    let velocity = screen_place - camera.origin;
+//Where within the pixel the rays of this sample go through, the center when jitter is off.
+//Every pixel shifts the Halton points by its own random amount, so neighbouring pixels don't share a pattern.
+fn pixel_offset(index: u32) -> vec2<f32> {
+    if (params.jitter == 0u) {
+        return vec2<f32>(0.5);
+    }
+    let point = vec2<f32>(halton(params.sample_index + 1u, 2u), halton(params.sample_index + 1u, 3u));
+    let seed = hash_u32(index ^ 0x9e3779b9u);
+    let shifted = point + vec2<f32>(random_float(seed), random_float(hash_u32(seed)));
+    return shifted - floor(shifted);
+}
+
+fn debug_color(ray: Ray) -> vec4<f32> {
+    let hit = ray.hit_index >= 0;
+    let point = ray.origin + ray.velocity * ray.distance;
+
+    switch debug_view.mode {
+        case DEBUG_NORMALS: {
+            if (!hit) {
+                return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+            }
+            return vec4<f32>(cube_normal(cubes[ray.hit_index], point) * 0.5 + 0.5, 1.0);
+        }
+        case DEBUG_DEPTH: {
+            if (!hit) {
+                return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+            }
+            let depth = 1.0 - clamp(ray.distance * length(ray.velocity) / debug_view.max_depth, 0.0, 1.0);
+            return vec4<f32>(depth, depth, depth, 1.0);
+        }
+        case DEBUG_VOXEL_ID: {
+            if (!hit) {
+                return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+            }
+            let h = hash_u32(u32(ray.hit_index));
+            return vec4<f32>(
+                f32(h & 255u) / 255.0,
+                f32((h >> 8u) & 255u) / 255.0,
+                f32((h >> 16u) & 255u) / 255.0,
+                1.0,
+            );
+        }
+        case DEBUG_BOXES_HIT: {
+            return vec4<f32>(heatmap(f32(ray.boxes_hit) / f32(debug_view.heatmap_max_boxes)), 1.0);
+        }
+        case DEBUG_CHUNK_BOUNDS: {
+            if (!hit) {
+                return ray.color;
+            }
+            let in_chunk = point.xy - floor(point.xy / debug_view.chunk_size) * debug_view.chunk_size;
+            let line_width = 0.1;
+            if (any(in_chunk < vec2<f32>(line_width)) || any(in_chunk > vec2<f32>(debug_view.chunk_size - line_width))) {
+                return vec4<f32>(1.0, 0.0, 0.0, 1.0);
+            }
+            return ray.color;
+        }
+        default: {
+            return ray.color;
+        }
+    }
+}
+
+//Traces a single ray through the given point in pixel coordinates, the seed picks its spot on the lens.
+fn trace_sample(pixel: vec2<f32>, seed: u32) -> vec4<f32> {
+    //The image plane is one unit in front of the camera, so its half height is the tangent of half the fov.
+    let plane_center = camera.origin + camera.forward_vec;
+    let half_height = camera.tan_half_fov_y;
+    let half_width = half_height * camera.aspect_ratio;
+
+    //Row 0 of screen_pixels ends up at the bottom of the screen, so the rows go up from the bottom left corner.
+    let bottom_left = plane_center + camera.left_vec * half_width - camera.up_vec * half_height;
+
+    let v = pixel.y / f32(params.screen_height);
+    let u = pixel.x / f32(params.screen_width);
+
+    let screen_place = bottom_left - camera.left_vec * u * 2.0 * half_width + camera.up_vec * v * 2.0 * half_height;
+
+    var origin = camera.origin;
+    var velocity = screen_place - camera.origin;
+
+    switch camera.projection {
+        case PROJECTION_ORTHOGRAPHIC: {
+            //Parallel rays starting on a plane through the camera that is ortho_height units high.
+            let ortho_half_height = camera.ortho_height * 0.5;
+            let ortho_half_width = ortho_half_height * camera.aspect_ratio;
+            origin = camera.origin + camera.left_vec * (ortho_half_width * (1.0 - 2.0 * u)) + camera.up_vec * (ortho_half_height * (2.0 * v - 1.0));
+            velocity = camera.forward_vec;
+        }
+        case PROJECTION_EQUIRECTANGULAR: {
+            //u goes all the way around up_vec starting behind the camera, v from straight down to straight up.
+            let longitude = (u - 0.5) * 2.0 * PI;
+            let latitude = (v - 0.5) * PI;
+            velocity = (camera.forward_vec * cos(longitude) - camera.left_vec * sin(longitude)) * cos(latitude) + camera.up_vec * sin(latitude);
+        }
+        default: {
+            //Thin lens, every sample starts somewhere else on the aperture and the rays meet again on the focus plane.
+            if (camera.aperture_radius > 0.0) {
+                let radius = camera.aperture_radius * sqrt(random_float(seed));
+                let angle = 2.0 * PI * random_float(hash_u32(seed));
+                let focus_point = camera.origin + velocity * camera.focus_distance;
+                origin = camera.origin + camera.left_vec * (radius * cos(angle)) + camera.up_vec * (radius * sin(angle));
+                velocity = focus_point - origin;
+            }
+        }
+    }
 
     var ray: Ray = Ray(
-        camera.origin,
+        origin,
         velocity,
         maxfloat,
-        vec4<f32>(v, u, amount_of_cubes, 1.0),
+        params.background_rgba,
+        -1,
+        0u,
     );
 
-    for (var i: i32 = 0; i < i32(amount_of_cubes); i = i + 1){
-        ray = intersect_ray(cubes[i], ray);
+    for (var i: i32 = 0; i < i32(params.amount_of_cubes); i = i + 1){
+        ray = intersect_ray(cubes[i], i, ray);
     }
-    
-    
 
-    screen_pixels[index] = ray.color;
-}
\ No newline at end of file
+    if (debug_view.mode != DEBUG_OFF) {
+        return debug_color(ray);
+    }
+    return ray.color;
+}
+
+@compute
+@workgroup_size(64)
+fn main(@builtin(global_invocation_id) global_invocation_id: vec3<u32>) {
+    let total = arrayLength(&screen_pixels);
+    let index = global_invocation_id.x + params.screen_width * global_invocation_id.y;
+
+    if (global_invocation_id.x >= params.screen_width || index >= total) {
+        return;
+    }
+
+    //Supersampling spreads its rays over a grid within the pixel, the grid as a whole moves with the jitter.
+    let offset = pixel_offset(index);
+    let n = params.supersampling;
+    var color = vec4<f32>(0.0);
+    for (var sy: u32 = 0u; sy < n; sy = sy + 1u) {
+        for (var sx: u32 = 0u; sx < n; sx = sx + 1u) {
+            let sub_pixel = (vec2<f32>(f32(sx), f32(sy)) + offset) / f32(n);
+            let seed = index ^ hash_u32(params.sample_index * n * n + sy * n + sx);
+            color = color + trace_sample(vec2<f32>(f32(global_invocation_id.x), f32(global_invocation_id.y)) + sub_pixel, seed);
+        }
+    }
+    color = color / f32(n * n);
+
+    //Running average over all samples taken so far.
+    if (params.sample_index > 0u) {
+        color = mix(screen_pixels[index], color, 1.0 / f32(params.sample_index + 1u));
+    }
+
+    screen_pixels[index] = color;
+}
@@ -2,13 +2,12 @@ use std::mem;
 
 use wgpu::util::DeviceExt;
 
-use crate::texture::Texture;
+use crate::{camera::Camera, render_context::RenderContext, texture::Texture};
 
-use super::{cube::Cube, render_image::RenderImage, scene::Scene, tracing_camera::{TracingCamera, TracingCameraController}};
+use super::{chunk::CHUNK_SIZE, cpu_tracer::CpuTracer, cube::Cube, debug_view::{DebugUniform, DebugView}, render_image::RenderImage, scene::Scene, tracing_camera::TracingCameraUniform};
 
 pub struct PTRender {
-    pub camera: TracingCamera,
-    pub camera_controller: TracingCameraController,
+    pub camera: Camera,
     pub scene: Scene,
     pub bind_group_layout: wgpu::BindGroupLayout,
     pub bind_group: wgpu::BindGroup,
@@ -22,31 +21,94 @@ pub struct PTRender {
     pub cube_bind_group: wgpu::BindGroup,
     pub cube_buffer: wgpu::Buffer,
     pub compute_pipeline: wgpu::ComputePipeline,
+    pub compute_params: ComputeParams,
     pub compute_param_buffer: wgpu::Buffer,
     pub compute_camera_buffer: wgpu::Buffer,
     pub compute_texture_output_buffer: wgpu::Buffer,
+    pub debug_uniform: DebugUniform,
+    pub compute_debug_buffer: wgpu::Buffer,
 }
 
-const MAX_CUBES: u32 = 200000;
+pub const MAX_CUBES: u32 = 200000;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ComputeParams {
+    pub amount_of_cubes: f32,
+    pub screen_width: u32,
+    pub screen_height: u32,
+    pub sample_index: u32, //Samples already accumulated in the output buffer, 0 overwrites it.
+    pub supersampling: u32, //Every pixel traces a supersampling x supersampling grid of rays per sample.
+    pub jitter: u32, //When not 0 the rays move around within their pixel every sample, so accumulating them anti-aliases.
+    pub _padding: [u32; 2],
+    pub background_rgba: [f32; 4], //Color of the rays that don't hit anything.
+}
+
+impl ComputeParams {
+    pub fn new(amount_of_cubes: usize, background_rgba: [f32; 4], screen_size: [usize; 2]) -> Self {
+        Self {
+            amount_of_cubes: amount_of_cubes as f32,
+            screen_width: screen_size[0] as u32,
+            screen_height: screen_size[1] as u32,
+            sample_index: 0,
+            supersampling: 1,
+            jitter: 0,
+            _padding: [0; 2],
+            background_rgba,
+        }
+    }
+}
 
 impl PTRender {
+    //The output buffer has an rgba f32 per pixel and is bound as a single storage buffer, so its size is limited by the device.
+    pub const BYTES_PER_PIXEL: u64 = 16;
+
+    pub fn max_pixels(limits: &wgpu::Limits) -> u64 {
+        (limits.max_storage_buffer_binding_size as u64).min(limits.max_buffer_size) / Self::BYTES_PER_PIXEL
+    }
+
+    //llvmpipe, the software adapter, stops a shader's loops after this many iterations in total.
+    //Past it the rays skip the remaining cubes, so the image comes out wrong without any error.
+    pub const SOFTWARE_LOOP_LIMIT: u64 = 65535;
+
+    //Iterations of the cube loop every pixel goes through, every cube for every supersampled ray.
+    pub fn loop_iterations(&self) -> u64 {
+        self.compute_params.amount_of_cubes as u64 * (self.compute_params.supersampling as u64).pow(2)
+    }
+
+    //Why the image will be wrong on this adapter, None when it traces the whole scene.
+    pub fn software_limit_warning(&self, context: &RenderContext) -> Option<String> {
+        (context.is_software() && self.loop_iterations() > Self::SOFTWARE_LOOP_LIMIT).then(|| format!(
+            "{} cubes times {}² rays per pixel is over the {} loop iterations a software adapter runs, part of the scene won't be drawn",
+            self.compute_params.amount_of_cubes, self.compute_params.supersampling, Self::SOFTWARE_LOOP_LIMIT,
+        ))
+    }
+
     pub fn new(
         device : &wgpu::Device,
-        config: &wgpu::SurfaceConfiguration,
         queue: &wgpu::Queue,
+        output_format: wgpu::TextureFormat,
+        screen_size: [usize; 2],
+    ) -> Self {
+        Self::with_scene(device, queue, output_format, screen_size, Scene::new())
+    }
+
+    pub fn with_scene(
+        device : &wgpu::Device,
+        queue: &wgpu::Queue,
+        output_format: wgpu::TextureFormat,
+        screen_size: [usize; 2],
+        mut scene: Scene,
     ) -> Self {
 
-        let camera = TracingCamera::new(
+        let camera = Camera::new(
             [0.0, 5.0, 0.0],
-            3.0,
-            [1920, 1080],
+            Camera::DEFAULT_FOV_Y,
+            screen_size,
             [0.0, 0.0, 0.0]
         );
-        let camera_controller = TracingCameraController::new();
 
-        let scene = Scene::new();
-        //TODO!: Make it so I do not need to render the scene on cpu for the original Render_image...
-        let render_texture = Texture::create_buffer_from_pixel_vec(device, queue, &camera.render_scene_cpu(&Scene::empty_scene()), "PTRender Texture");
+        let render_texture = Texture::create_buffer_from_pixel_vec(device, queue, &RenderImage::new(screen_size[0], screen_size[1]), "PTRender Texture");
 
         //Texture render stuffs
 
@@ -112,7 +174,7 @@ impl PTRender {
                 module: &shader, entry_point: "fs_main", 
                 compilation_options: wgpu::PipelineCompilationOptions::default(),
                 targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
+                    format: output_format,
                     blend: Some(wgpu::BlendState::REPLACE),
                     write_mask: wgpu::ColorWrites::ALL,
                 })]
@@ -179,7 +241,7 @@ impl PTRender {
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Uniform,
                         has_dynamic_offset: false,
-                        min_binding_size: wgpu::BufferSize::new(mem::size_of::<f32>() as _),
+                        min_binding_size: wgpu::BufferSize::new(mem::size_of::<ComputeParams>() as _),
                     },
                     count: None,
                 },
@@ -189,7 +251,7 @@ impl PTRender {
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Uniform,
                         has_dynamic_offset: false,
-                        min_binding_size: wgpu::BufferSize::new(16 * (mem::size_of::<f32>() as u64)),
+                        min_binding_size: wgpu::BufferSize::new(mem::size_of::<TracingCameraUniform>() as _),
                     },
                     count: None,
                 },
@@ -213,6 +275,16 @@ impl PTRender {
                     },
                     count: None,
                 },
+                wgpu::BindGroupLayoutEntry { //Debug view
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(mem::size_of::<DebugUniform>() as _),
+                    },
+                    count: None,
+                },
             ],
             label: Some("PT Compute bind group layout")
         });
@@ -241,9 +313,9 @@ impl PTRender {
             ];
             
         
-        for i in 0..scene.cubes.len() {
-            initial_cube_data[i] = scene.cubes[i];
-        }
+        let amount_of_cubes = scene.cubes.len().min(MAX_CUBES as usize);
+        initial_cube_data[..amount_of_cubes].copy_from_slice(&scene.cubes[..amount_of_cubes]);
+        scene.changed_from = None;
 
         
         let cube_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -252,40 +324,21 @@ impl PTRender {
             usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
         });
 
-        let amount_of_cubes = scene.cubes.len() as f32;
+        let compute_params = ComputeParams::new(amount_of_cubes, scene.background_rgba, screen_size);
 
         let compute_param_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Compute Amount of Cubes buffer"),
-            contents: bytemuck::cast_slice(&[amount_of_cubes]),
+            label: Some("Compute Params buffer"),
+            contents: bytemuck::cast_slice(&[compute_params]),
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
-        let camera_vectors = [
-            camera.origin[0],
-            camera.origin[1],
-            camera.origin[2],
-            0.0, //Padding
-            camera.forward_vec[0],
-            camera.forward_vec[1],
-            camera.forward_vec[2],
-            0.0, //Padding
-            camera.left_vec[0],
-            camera.left_vec[1],
-            camera.left_vec[2],
-            0.0, //Padding
-            camera.up_vec[0],
-            camera.up_vec[1],
-            camera.up_vec[2],
-            0.0 //Padding
-        ];
-
         let compute_camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Compute Camera Buffer"),
-            contents: bytemuck::cast_slice(&camera_vectors),
+            contents: bytemuck::cast_slice(&[camera.uniform()]),
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
-        let initial_output_values = vec![0.0; 1920 * 1080 * 4];
+        let initial_output_values = vec![0.0f32; screen_size[0] * screen_size[1] * 4];
 
         let compute_texture_output_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Compute Texture output Buffer"),
@@ -293,6 +346,13 @@ impl PTRender {
             usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
         });
 
+        let debug_uniform = DebugUniform::new(DebugView::Off, CHUNK_SIZE as f32);
+
+        let compute_debug_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Compute Debug View Buffer"),
+            contents: bytemuck::cast_slice(&[debug_uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
 
         let cube_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout: &compute_bind_group_layout,
@@ -313,6 +373,10 @@ impl PTRender {
                     binding: 3,
                     resource: compute_texture_output_buffer.as_entire_binding(),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: compute_debug_buffer.as_entire_binding(),
+                },
 
             ],
             label: Some("Compute Bind group"),
@@ -320,7 +384,6 @@ impl PTRender {
 
         Self {
             camera,
-            camera_controller,
             scene,
             bind_group_layout,
             bind_group,
@@ -333,9 +396,12 @@ impl PTRender {
             cube_bind_group,
             cube_buffer,
             compute_pipeline,
+            compute_params,
             compute_param_buffer,
             compute_camera_buffer,
-            compute_texture_output_buffer
+            compute_texture_output_buffer,
+            debug_uniform,
+            compute_debug_buffer,
         }
 
     }
@@ -344,26 +410,101 @@ impl PTRender {
         &self,
         queue: &wgpu::Queue,
     ) {
-        let camera_vectors = [
-            self.camera.origin[0],
-            self.camera.origin[1],
-            self.camera.origin[2],
-            0.0, //Padding
-            self.camera.forward_vec[0],
-            self.camera.forward_vec[1],
-            self.camera.forward_vec[2],
-            0.0, //Padding
-            self.camera.left_vec[0],
-            self.camera.left_vec[1],
-            self.camera.left_vec[2],
-            0.0, //Padding
-            self.camera.up_vec[0],
-            self.camera.up_vec[1],
-            self.camera.up_vec[2],
-            0.0 //Padding
-        ];
+        queue.write_buffer(&self.compute_camera_buffer, 0, bytemuck::cast_slice(&[self.camera.uniform()]));
+    }
 
-        queue.write_buffer(&self.compute_camera_buffer, 0, bytemuck::cast_slice(&[camera_vectors]));
+    //Uploads the cubes that changed since the last upload, after chunks were added to or removed from the scene.
+    //Cubes past the end are left in the buffer, amount_of_cubes keeps the shader from looking at them.
+    //Returns how many cubes of the scene don't fit in the buffer and aren't drawn, 0 when they all fit.
+    pub fn upload_scene(
+        &mut self,
+        queue: &wgpu::Queue,
+    ) -> usize {
+        let left_out = self.scene.cubes.len().saturating_sub(MAX_CUBES as usize);
+        let Some(changed_from) = self.scene.changed_from.take() else {
+            return left_out;
+        };
+        let amount_of_cubes = self.scene.cubes.len().min(MAX_CUBES as usize);
+        if changed_from < amount_of_cubes {
+            let offset = (changed_from * std::mem::size_of::<Cube>()) as wgpu::BufferAddress;
+            queue.write_buffer(&self.cube_buffer, offset, bytemuck::cast_slice(&self.scene.cubes[changed_from..amount_of_cubes]));
+        }
+        self.compute_params.amount_of_cubes = amount_of_cubes as f32;
+        queue.write_buffer(&self.compute_param_buffer, 0, bytemuck::cast_slice(&[self.compute_params]));
+        left_out
+    }
+
+    //A cpu tracer with the exact same inputs as the compute shader currently has.
+    pub fn cpu_tracer(&self) -> CpuTracer<'_> {
+        CpuTracer {
+            params: ComputeParams {
+                amount_of_cubes: self.scene.cubes.len().min(MAX_CUBES as usize) as f32,
+                ..self.compute_params
+            },
+            camera: self.camera.uniform(),
+            cubes: &self.scene.cubes,
+            debug_view: self.debug_uniform,
+        }
+    }
+
+    pub fn set_debug_view(
+        &mut self,
+        queue: &wgpu::Queue,
+        view: DebugView,
+    ) {
+        self.debug_uniform.mode = view as u32;
+        queue.write_buffer(&self.compute_debug_buffer, 0, bytemuck::cast_slice(&[self.debug_uniform]));
+    }
+
+    //Following compute passes average their result with what is already in the output buffer.
+    pub fn set_sample_index(
+        &mut self,
+        queue: &wgpu::Queue,
+        sample_index: u32,
+    ) {
+        self.compute_params.sample_index = sample_index;
+        queue.write_buffer(&self.compute_param_buffer, 0, bytemuck::cast_slice(&[self.compute_params]));
+    }
+
+    //Only useful together with accumulating samples, a single jittered sample is just noisier.
+    pub fn set_jitter(
+        &mut self,
+        queue: &wgpu::Queue,
+        jitter: bool,
+    ) {
+        self.compute_params.jitter = jitter as u32;
+        queue.write_buffer(&self.compute_param_buffer, 0, bytemuck::cast_slice(&[self.compute_params]));
+    }
+
+    //Anti-aliases within a single sample, at the cost of supersampling² rays per pixel.
+    pub fn set_supersampling(
+        &mut self,
+        queue: &wgpu::Queue,
+        supersampling: u32,
+    ) {
+        self.compute_params.supersampling = supersampling.max(1);
+        queue.write_buffer(&self.compute_param_buffer, 0, bytemuck::cast_slice(&[self.compute_params]));
+    }
+
+    fn encode_compute_pass(&self, command_encoder: &mut wgpu::CommandEncoder) {
+        let mut cpass = command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: None,
+            timestamp_writes: None,
+        });
+        cpass.set_pipeline(&self.compute_pipeline);
+        cpass.set_bind_group(0, &self.cube_bind_group, &[]);
+        cpass.dispatch_workgroups(self.compute_params.screen_width.div_ceil(64), self.compute_params.screen_height, 1);
+    }
+
+    //Only runs the path tracer into the output buffer, without copying it to the render texture.
+    pub fn dispatch_compute(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue
+    ) {
+        let mut command_encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Compute Encoder")});
+        self.encode_compute_pass(&mut command_encoder);
+        queue.submit(Some(command_encoder.finish()));
     }
 
     pub fn render_scene_gpu(
@@ -374,15 +515,7 @@ impl PTRender {
         
         let mut command_encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Compute Encoder")}); 
 
-        {
-            let mut cpass = command_encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-                label: None,
-                timestamp_writes: None,
-            });
-            cpass.set_pipeline(&self.compute_pipeline);
-            cpass.set_bind_group(0, &self.cube_bind_group, &[]);
-            cpass.dispatch_workgroups(1920 / 64, 1080, 1);
-        }
+        self.encode_compute_pass(&mut command_encoder);
 
         let texture_copy_view = wgpu::ImageCopyTexture {
             texture: &self.render_texture.texture,
@@ -395,14 +528,14 @@ impl PTRender {
             buffer: &self.compute_texture_output_buffer,
             layout: wgpu::ImageDataLayout {
                 offset: 0,
-                bytes_per_row: Some((1920 * 4 * 4) as u32),
-                rows_per_image: Some(1080 as u32),
+                bytes_per_row: Some(self.compute_params.screen_width * 4 * 4),
+                rows_per_image: Some(self.compute_params.screen_height),
             }
         };
 
         let size = wgpu::Extent3d {
-            width: 1920,
-            height: 1080,
+            width: self.compute_params.screen_width,
+            height: self.compute_params.screen_height,
             depth_or_array_layers: 1,
         };
 
@@ -410,6 +543,40 @@ impl PTRender {
 
         queue.submit(Some(command_encoder.finish()));
     }
+
+    //Reads the output of the last compute pass back to the cpu, blocks until the gpu is done with it.
+    pub fn capture(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> RenderImage {
+        //The size the output buffer was made for, the camera can have been swapped for one with another screen_size.
+        let [x_size, y_size] = [self.compute_params.screen_width as usize, self.compute_params.screen_height as usize];
+        let size = (x_size * y_size * mem::size_of::<[f32; 4]>()) as wgpu::BufferAddress;
+
+        let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Capture staging buffer"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut command_encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Capture Encoder")});
+        command_encoder.copy_buffer_to_buffer(&self.compute_texture_output_buffer, 0, &staging_buffer, 0, size);
+        queue.submit(Some(command_encoder.finish()));
+
+        let buffer_slice = staging_buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        buffer_slice.map_async(wgpu::MapMode::Read, move |result| sender.send(result).unwrap());
+        device.poll(wgpu::Maintain::Wait);
+        receiver.recv().unwrap().expect("Failed to map the capture buffer");
+
+        let mut render_image = RenderImage::new(x_size, y_size);
+        render_image.pixels.copy_from_slice(bytemuck::cast_slice(&buffer_slice.get_mapped_range()));
+        staging_buffer.unmap();
+
+        render_image
+    }
 }
 
 
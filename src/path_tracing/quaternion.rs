@@ -1,21 +1,37 @@
-// File made my chatgpt, looks good afaik
-//Todo!: Properly learn quaternions.
+use std::ops::Mul;
 
-#[derive(Debug, Clone, Copy)]
+use super::vector_funcs::{cross_vector, normalize_vector, vector_length};
+
+//Rotations as quaternions. Everything except inverse and normalize assumes a unit quaternion.
+//Euler angles and matrices use the camera's convention: z is up, x forward and y left,
+//yaw turns counterclockwise around z, pitch looks up and roll turns around forward.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Quaternion {
-    w: f32,  // Scalar part
-    x: f32,  // X component of the vector part
-    y: f32,  // Y component of the vector part
-    z: f32,  // Z component of the vector part
+    pub w: f32, //Scalar part.
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+//3x3 rotation matrix stored as columns, so m[0] is where x ends up.
+pub type RotationMatrix = [[f32; 3]; 3];
+
+impl Default for Quaternion {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
 }
 
 impl Quaternion {
-    // Create a new quaternion
+    pub const IDENTITY: Quaternion = Quaternion { w: 1.0, x: 0.0, y: 0.0, z: 0.0 };
+
+    //Below this the rotation is too small for slerp to divide by its sine.
+    const NLERP_THRESHOLD: f32 = 0.9995;
+
     pub fn new(w: f32, x: f32, y: f32, z: f32) -> Self {
         Self { w, x, y, z }
     }
 
-    // Multiply two quaternions
     pub fn multiply(self, other: Quaternion) -> Quaternion {
         Quaternion {
             w: self.w * other.w - self.x * other.x - self.y * other.y - self.z * other.z,
@@ -25,19 +41,29 @@ impl Quaternion {
         }
     }
 
-    // Conjugate of a quaternion (needed for inversion since unit quaternions have norm 1)
+    //The inverse of a unit quaternion, cheaper than inverse.
     pub fn conjugate(self) -> Quaternion {
         Quaternion::new(self.w, -self.x, -self.y, -self.z)
     }
 
-    // Rotate a 3D vector by this quaternion
+    pub fn inverse(self) -> Quaternion {
+        let length_squared = self.dot(self);
+        let conjugate = self.conjugate();
+        Quaternion::new(
+            conjugate.w / length_squared,
+            conjugate.x / length_squared,
+            conjugate.y / length_squared,
+            conjugate.z / length_squared,
+        )
+    }
+
     pub fn rotate_vector(self, vector: [f32; 3]) -> [f32; 3] {
         let q_vec = Quaternion::new(0.0, vector[0], vector[1], vector[2]);
         let q_rotated = self.multiply(q_vec).multiply(self.conjugate());
         [q_rotated.x, q_rotated.y, q_rotated.z]
     }
 
-    // Create a unit quaternion from an axis and an angle (radians)
+    //Counterclockwise around axis when looking down on it, the axis has to be normalized.
     pub fn from_axis_angle(axis: [f32; 3], angle: f32) -> Self {
         let half_angle = angle / 2.0;
         let (sin_half_angle, cos_half_angle) = half_angle.sin_cos();
@@ -48,4 +74,154 @@ impl Quaternion {
             axis[2] * sin_half_angle,
         )
     }
-}
\ No newline at end of file
+
+    //Angle in radians, 0..2pi.
+    pub fn angle(self) -> f32 {
+        2.0 * self.w.clamp(-1.0, 1.0).acos()
+    }
+
+    pub fn dot(self, other: Quaternion) -> f32 {
+        self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn length(self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    pub fn normalize(self) -> Quaternion {
+        let length = self.length();
+        Quaternion::new(self.w / length, self.x / length, self.y / length, self.z / length)
+    }
+
+    //q and -q are the same rotation, interpolating towards the one closest to self takes the short way around.
+    fn closest(self, other: Quaternion) -> Quaternion {
+        if self.dot(other) < 0.0 {
+            Quaternion::new(-other.w, -other.x, -other.y, -other.z)
+        } else {
+            other
+        }
+    }
+
+    //Normalized linear interpolation along the shortest arc.
+    //Cheaper than slerp, but the speed of the rotation isn't constant over t.
+    pub fn nlerp(self, other: Quaternion, t: f32) -> Quaternion {
+        let other = self.closest(other);
+        Quaternion::new(
+            self.w + (other.w - self.w) * t,
+            self.x + (other.x - self.x) * t,
+            self.y + (other.y - self.y) * t,
+            self.z + (other.z - self.z) * t,
+        ).normalize()
+    }
+
+    //Spherical interpolation along the shortest arc, rotates at a constant speed over t.
+    pub fn slerp(self, other: Quaternion, t: f32) -> Quaternion {
+        let other = self.closest(other);
+        let cos_theta = self.dot(other);
+        if cos_theta > Self::NLERP_THRESHOLD {
+            return self.nlerp(other, t);
+        }
+
+        let theta = cos_theta.acos();
+        let sin_theta = theta.sin();
+        let a = ((1.0 - t) * theta).sin() / sin_theta;
+        let b = (t * theta).sin() / sin_theta;
+
+        Quaternion::new(
+            a * self.w + b * other.w,
+            a * self.x + b * other.x,
+            a * self.y + b * other.y,
+            a * self.z + b * other.z,
+        ).normalize()
+    }
+
+    pub fn to_rotation_matrix(self) -> RotationMatrix {
+        let Quaternion { w, x, y, z } = self;
+        [
+            [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y + w * z), 2.0 * (x * z - w * y)],
+            [2.0 * (x * y - w * z), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z + w * x)],
+            [2.0 * (x * z + w * y), 2.0 * (y * z - w * x), 1.0 - 2.0 * (x * x + y * y)],
+        ]
+    }
+
+    //The matrix has to be a rotation, orthonormal without any scale or mirroring.
+    pub fn from_rotation_matrix(m: RotationMatrix) -> Self {
+        //m[column][row], named by row and column below to match the usual formulas.
+        let (m00, m01, m02) = (m[0][0], m[1][0], m[2][0]);
+        let (m10, m11, m12) = (m[0][1], m[1][1], m[2][1]);
+        let (m20, m21, m22) = (m[0][2], m[1][2], m[2][2]);
+
+        //Divides by the largest of w, x, y and z so precision doesn't get lost close to 180 degrees.
+        let trace = m00 + m11 + m22;
+        let q = if trace > 0.0 {
+            let s = (trace + 1.0).sqrt() * 2.0;
+            Quaternion::new(0.25 * s, (m21 - m12) / s, (m02 - m20) / s, (m10 - m01) / s)
+        } else if m00 > m11 && m00 > m22 {
+            let s = (1.0 + m00 - m11 - m22).sqrt() * 2.0;
+            Quaternion::new((m21 - m12) / s, 0.25 * s, (m01 + m10) / s, (m02 + m20) / s)
+        } else if m11 > m22 {
+            let s = (1.0 + m11 - m00 - m22).sqrt() * 2.0;
+            Quaternion::new((m02 - m20) / s, (m01 + m10) / s, 0.25 * s, (m12 + m21) / s)
+        } else {
+            let s = (1.0 + m22 - m00 - m11).sqrt() * 2.0;
+            Quaternion::new((m10 - m01) / s, (m02 + m20) / s, (m12 + m21) / s, 0.25 * s)
+        };
+        q.normalize()
+    }
+
+    //Yaw around z, then pitch up around the turned y axis, then roll around the turned x axis.
+    pub fn from_euler(yaw: f32, pitch: f32, roll: f32) -> Self {
+        let yaw = Quaternion::from_axis_angle([0.0, 0.0, 1.0], yaw);
+        let pitch = Quaternion::from_axis_angle([0.0, -1.0, 0.0], pitch);
+        let roll = Quaternion::from_axis_angle([1.0, 0.0, 0.0], roll);
+        yaw * pitch * roll
+    }
+
+    //[yaw, pitch, roll] with yaw and roll in -pi..pi and pitch in -pi/2..pi/2.
+    //Looking straight up or down yaw and roll turn around the same axis, then all of it goes into yaw.
+    pub fn to_euler(self) -> [f32; 3] {
+        let m = self.to_rotation_matrix();
+        let pitch = m[0][2].clamp(-1.0, 1.0).asin();
+
+        let cos_pitch = (m[0][0] * m[0][0] + m[0][1] * m[0][1]).sqrt();
+        if cos_pitch < 1e-6 {
+            let yaw = (-m[1][0]).atan2(m[1][1]);
+            return [yaw, pitch, 0.0];
+        }
+
+        let yaw = m[0][1].atan2(m[0][0]);
+        let roll = m[1][2].atan2(m[2][2]);
+        [yaw, pitch, roll]
+    }
+
+    //Rotation that turns x towards forward with z as close to up as possible, like a camera looking along forward.
+    //Neither has to be normalized. When forward is parallel to up, x (or y if forward is along x) is used as up instead.
+    pub fn look_rotation(forward: [f32; 3], up: [f32; 3]) -> Self {
+        let forward = normalize_vector(&forward);
+        let mut left = cross_vector(&up, &forward);
+        if vector_length(&left) < 1e-6 {
+            let fallback_up = if forward[0].abs() < 0.9 { [1.0, 0.0, 0.0] } else { [0.0, 1.0, 0.0] };
+            left = cross_vector(&fallback_up, &forward);
+        }
+        let left = normalize_vector(&left);
+        let up = cross_vector(&forward, &left);
+        Self::from_rotation_matrix([forward, left, up])
+    }
+}
+
+impl Mul for Quaternion {
+    type Output = Quaternion;
+
+    //a * b rotates by b first and then by a.
+    fn mul(self, other: Quaternion) -> Quaternion {
+        self.multiply(other)
+    }
+}
+
+impl Mul<[f32; 3]> for Quaternion {
+    type Output = [f32; 3];
+
+    fn mul(self, vector: [f32; 3]) -> [f32; 3] {
+        self.rotate_vector(vector)
+    }
+}
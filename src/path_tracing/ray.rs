@@ -3,4 +3,6 @@ pub struct Ray {
     pub velocity: [f32; 3],
     pub distance: f32,
     pub color: [f32; 4],
+    pub hit_index: i32, //Index of the closest cube that was hit, -1 if nothing was hit.
+    pub boxes_hit: u32, //Amount of cubes the ray passed through, used by the boxes hit view.
 }
\ No newline at end of file
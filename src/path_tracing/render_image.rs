@@ -1,3 +1,6 @@
+use std::{fs::File, io::{self, BufWriter, Write}, path::Path};
+
+//Pixels are stored row by row starting at the bottom of the screen, the same layout as the texture the path tracer writes into.
 pub struct RenderImage {
     pub x_size: usize,
     pub y_size: usize,
@@ -11,7 +14,79 @@ impl RenderImage {
         Self {
             x_size: x_dimension,
             y_size: y_dimension,
-            pixels: pixels,
+            pixels,
+        }
+    }
+
+    //Rows in the order they are shown on screen, top row first.
+    fn rows_top_down(&self) -> impl Iterator<Item = &[[f32; 4]]> {
+        self.pixels.chunks_exact(self.x_size).rev()
+    }
+
+    //Tightly packed 8-bit sRGB rgb values, top row first. This is what save_png writes.
+    pub fn to_srgb8(&self) -> Vec<u8> {
+        let mut data: Vec<u8> = Vec::with_capacity(self.x_size * self.y_size * 3);
+        for row in self.rows_top_down() {
+            for pixel in row {
+                data.extend_from_slice(&[
+                    linear_to_srgb_u8(pixel[0]),
+                    linear_to_srgb_u8(pixel[1]),
+                    linear_to_srgb_u8(pixel[2]),
+                ]);
+            }
         }
+        data
     }
-} 
\ No newline at end of file
+
+    //8-bit sRGB png, tonemapped the same way the window shows it: clamped to [0, 1] and gamma encoded.
+    pub fn save_png(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let file = File::create(path)?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.x_size as u32, self.y_size as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.to_srgb8())?;
+        writer.finish()?;
+        Ok(())
+    }
+
+    //Linear float RGBA OpenEXR.
+    pub fn save_exr(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        exr::prelude::write_rgba_file(
+            path,
+            self.x_size,
+            self.y_size,
+            |x, y| {
+                let pixel = self.pixels[(self.y_size - 1 - y) * self.x_size + x];
+                (pixel[0], pixel[1], pixel[2], pixel[3])
+            },
+        ).map_err(io::Error::other)
+    }
+
+    //Linear float RGB portable float map. Pfm stores its rows bottom to top, so they can be written out as is.
+    pub fn save_pfm(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        //A negative scale marks the data as little endian.
+        write!(writer, "PF\n{} {}\n-1.0\n", self.x_size, self.y_size)?;
+
+        for pixel in &self.pixels {
+            for channel in &pixel[0..3] {
+                writer.write_all(&channel.to_le_bytes())?;
+            }
+        }
+
+        writer.flush()
+    }
+}
+
+fn linear_to_srgb_u8(value: f32) -> u8 {
+    let v = value.clamp(0.0, 1.0);
+    let srgb = if v <= 0.0031308 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    };
+    (srgb * 255.0).round() as u8
+}
@@ -1,81 +1,130 @@
-use rand::Rng;
+use std::ops::Range;
+
+use crate::{camera::Camera, terrain::{NoiseTerrain, TerrainGenerator}};
 
 use super::{chunk::PTObject, cube::Cube, ray::Ray};
 
 pub struct Scene {
     pub cubes: Vec<Cube>,
     pub background_rgba: [f32; 4],
-    pub chunk_grid: Vec<bool>, //The way I have made it now makes it kind of unnessecary for this grid to exist, as it will always be true if teh chunks are loaded in.
-    pub grid_size: usize, //The amount of Chunks in a direction. (Note the render distance is this value / 2, as we support negative values as well)
+    pub chunk_grid: Vec<Option<SceneChunk>>, //Wraps around, see chunk_xy_to_grid_location.
+    pub grid_size: usize, //The amount of Chunks in a direction, twice the render distance plus the chunk in the middle.
+    pub changed_from: Option<usize>, //First cube that changed since the cubes were last uploaded to the gpu.
 }
 
-fn chunk_xy_to_grid_location(grid_size: &usize, chunk_x: &i32, chunk_y: &i32) -> usize {
+//A loaded chunk and where its cubes are in Scene::cubes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SceneChunk {
+    pub position: [i32; 2],
+    pub cubes: Range<usize>,
+}
 
-    let grid_y = (grid_size / 2) as i32 + chunk_y;
-    let grid_x = (grid_size / 2) as i32 + chunk_x;
+//The grid is a torus, a chunk goes in the slot of its coordinates modulo the grid size.
+//Any grid_size by grid_size square of chunks fills every slot exactly once, so the camera can move anywhere without the grid moving along.
+fn chunk_xy_to_grid_location(grid_size: &usize, chunk_x: &i32, chunk_y: &i32) -> usize {
+    let grid_y = chunk_y.rem_euclid(*grid_size as i32);
+    let grid_x = chunk_x.rem_euclid(*grid_size as i32);
     grid_y as usize * grid_size + grid_x as usize
 }
 
-impl Scene {
+impl Default for Scene {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
+impl Scene {
     pub fn new() -> Self {
+        Self::with_generator(&NoiseTerrain::default())
+    }
 
-        let mut cubes: Vec<Cube> = vec![];
-
-        let grid_size = 16;
-
-        let mut chunk_grid: Vec<u32> = vec![0; grid_size * grid_size];
+    //Same cubes and colors for the same seed, which is what the golden image tests rely on.
+    pub fn with_seed(seed: u32) -> Self {
+        Self::with_generator(&NoiseTerrain::with_seed(seed))
+    }
 
-        // for _ in 0..10 {
-        //     let mut rng = rand::thread_rng();
-        //     let random_location: [f32; 3] = [rng.gen_range(0..25) as f32, rng.gen_range(0..25) as f32, rng.gen_range(0..25) as f32];
-        //     let random_color: [f32; 4] = [rng.gen_range(0..100) as f32 / 100f32, rng.gen_range(0..100) as f32 / 100f32, rng.gen_range(0..100) as f32 / 100f32, 1.0];
-        //     cubes.push(Cube::new_cube_at(&random_location, random_color));
-        // }
-        for x in 0..1 {
-            for y in 0..1 {
-                cubes.extend_from_slice(PTObject::new(x, y).get_cubes());
-                let index = chunk_xy_to_grid_location(&grid_size, &x, &y);
-                chunk_grid[index] = 0;
-                println!("Cube len: {:?}", cubes.len());
-            }
-        }
-        
-        Self {
-            cubes: cubes,
-            background_rgba: [0.4, 0.5, 0.6, 1.0],
-            chunk_grid: chunk_grid,
-            grid_size: grid_size,
-        }
+    //Only the chunk at the origin, the path tracer goes over every cube so more would make it a lot slower.
+    pub fn with_generator(generator: &dyn TerrainGenerator) -> Self {
+        let mut scene = Self::empty_scene();
+        scene.add_chunk([0, 0], &PTObject::new(&generator.generate_chunk(0, 0)));
+        scene
     }
 
     pub fn empty_scene() -> Self {
-        let grid_size = 16;
+        Self::with_render_distance(8)
+    }
 
-        let chunk_grid: Vec<bool> = vec![false; grid_size * grid_size];
+    //Room for every chunk up to render_distance chunks away from the chunk in the middle, along both x and y.
+    pub fn with_render_distance(render_distance: i32) -> Self {
+        let grid_size = 2 * render_distance.max(0) as usize + 1;
+
+        let chunk_grid: Vec<Option<SceneChunk>> = vec![None; grid_size * grid_size];
 
         Self {
             cubes: vec![],
             background_rgba: [0.4, 0.5, 0.6, 1.0],
-            chunk_grid: chunk_grid,
-            grid_size: grid_size,
+            chunk_grid,
+            grid_size,
+            changed_from: None,
         }
     }
 
-    //Maybe rename to albedo in future, if we have ligthing etc.
-    pub fn get_color(&self, mut ray: Ray) -> [f32; 4]{
+    pub fn is_loaded(&self, position: [i32; 2]) -> bool {
+        let index = chunk_xy_to_grid_location(&self.grid_size, &position[0], &position[1]);
+        self.chunk_grid[index].as_ref().is_some_and(|chunk| chunk.position == position)
+    }
+
+    //The cubes get added to the end, replacing whatever chunk was in its slot of the grid before.
+    pub fn add_chunk(&mut self, position: [i32; 2], object: &PTObject) {
+        let index = chunk_xy_to_grid_location(&self.grid_size, &position[0], &position[1]);
+        if let Some(old) = &self.chunk_grid[index] {
+            self.remove_chunk(old.position);
+        }
 
-        let mut rgba = self.background_rgba;
+        let start = self.cubes.len();
+        self.cubes.extend_from_slice(object.get_cubes());
+        self.chunk_grid[index] = Some(SceneChunk { position, cubes: start..self.cubes.len() });
+        self.mark_changed(start);
+    }
 
-        for cube in &self.cubes {
-            cube.intersect_ray(&mut ray);
+    //The cubes after the chunk's move down to fill the gap, so everything from there on has to be uploaded again.
+    pub fn remove_chunk(&mut self, position: [i32; 2]) -> bool {
+        let index = chunk_xy_to_grid_location(&self.grid_size, &position[0], &position[1]);
+        let Some(removed) = self.chunk_grid[index].take_if(|chunk| chunk.position == position) else {
+            return false;
+        };
+
+        self.cubes.drain(removed.cubes.clone());
+        let amount = removed.cubes.len();
+        for chunk in self.chunk_grid.iter_mut().flatten() {
+            if chunk.cubes.start >= removed.cubes.end {
+                chunk.cubes = chunk.cubes.start - amount..chunk.cubes.end - amount;
+            }
         }
+        self.mark_changed(removed.cubes.start);
+        true
+    }
 
-        if ray.distance < f32::MAX {
-            rgba = ray.color;
+    //Distance to focus on for the voxel under the center of the camera's screen, None if nothing is there.
+    pub fn autofocus(&self, camera: &Camera) -> Option<f32> {
+        let mut ray = Ray {
+            origin: camera.origin,
+            velocity: camera.forward_vec,
+            distance: f32::MAX,
+            color: [0.0; 4],
+            hit_index: -1,
+            boxes_hit: 0,
+        };
+
+        for (i, cube) in self.cubes.iter().enumerate() {
+            cube.intersect_ray(i as i32, &mut ray);
         }
 
-        rgba
+        //forward_vec has length one, so the distance along the ray is also the distance to the focus plane.
+        (ray.hit_index >= 0).then_some(ray.distance)
     }
-}
 
+    fn mark_changed(&mut self, from: usize) {
+        self.changed_from = Some(self.changed_from.map_or(from, |changed| changed.min(from)));
+    }
+}
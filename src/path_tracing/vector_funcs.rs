@@ -3,6 +3,26 @@ pub fn normalize_vector(vector: &[f32; 3]) -> [f32; 3] {
     [vector[0] / magnitude, vector[1] / magnitude, vector[2] / magnitude]
 }
 
+pub fn add_vector(v: &[f32; 3], u: &[f32; 3]) -> [f32; 3] {
+    [v[0] + u[0], v[1] + u[1], v[2] + u[2]]
+}
+
+pub fn sub_vector(v: &[f32; 3], u: &[f32; 3]) -> [f32; 3] {
+    [v[0] - u[0], v[1] - u[1], v[2] - u[2]]
+}
+
+pub fn scale_vector(v: &[f32; 3], scalar: f32) -> [f32; 3] {
+    [v[0] * scalar, v[1] * scalar, v[2] * scalar]
+}
+
+pub fn vector_length(v: &[f32; 3]) -> f32 {
+    (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt()
+}
+
+pub fn dot_vector(v: &[f32; 3], u: &[f32; 3]) -> f32 {
+    v[0] * u[0] + v[1] * u[1] + v[2] * u[2]
+}
+
 pub fn cross_vector(v: &[f32; 3], u: &[f32; 3]) -> [f32; 3] {
     [
         v[1] * u[2] - v[2] * u[1],
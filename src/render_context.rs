@@ -0,0 +1,78 @@
+use std::fmt;
+
+//Everything needed to talk to the gpu, without requiring a window.
+//State builds one for its surface, offline rendering and tests use a headless one.
+pub struct RenderContext {
+    pub instance: wgpu::Instance,
+    pub adapter: wgpu::Adapter,
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+}
+
+#[derive(Debug)]
+pub enum RenderContextError {
+    NoAdapter,
+    RequestDevice(wgpu::RequestDeviceError),
+}
+
+impl fmt::Display for RenderContextError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderContextError::NoAdapter => write!(f, "No suitable gpu adapter found"),
+            RenderContextError::RequestDevice(e) => write!(f, "Failed to request a device: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for RenderContextError {}
+
+impl RenderContext {
+    //A cpu implementation like llvmpipe, which has limits a real gpu doesn't, see PTRender::SOFTWARE_LOOP_LIMIT.
+    pub fn is_software(&self) -> bool {
+        self.adapter.get_info().device_type == wgpu::DeviceType::Cpu
+    }
+
+    //Uses any backend that is available, so the window and headless rendering pick them the same way.
+    pub fn instance() -> wgpu::Instance {
+        wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::all(),
+            ..Default::default()
+        })
+    }
+
+    //With force_fallback_adapter a software adapter is picked, which is what CI machines without a gpu end up with.
+    pub async fn headless(force_fallback_adapter: bool) -> Result<Self, RenderContextError> {
+        Self::new(Self::instance(), None, force_fallback_adapter).await
+    }
+
+    //The surface has to be created from the same instance that is passed in.
+    pub async fn new(
+        instance: wgpu::Instance,
+        compatible_surface: Option<&wgpu::Surface<'_>>,
+        force_fallback_adapter: bool,
+    ) -> Result<Self, RenderContextError> {
+        let adapter = instance.request_adapter(
+            &wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                force_fallback_adapter,
+                compatible_surface,
+            }
+        ).await.ok_or(RenderContextError::NoAdapter)?;
+
+        let (device, queue) = adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                required_features: wgpu::Features::empty(),
+                required_limits: wgpu::Limits::default(),
+                label: None,
+            },
+            None,
+        ).await.map_err(RenderContextError::RequestDevice)?;
+
+        Ok(Self {
+            instance,
+            adapter,
+            device,
+            queue,
+        })
+    }
+}
@@ -1,9 +1,12 @@
 use winit::{
     window::Window,
     event::*,
+    keyboard::{KeyCode, PhysicalKey},
 };
 
-use crate::{camera::{Camera, CameraController}, objects::*, path_tracing::{pt_render::PTRender, tracing_camera::TracingCameraController}, texture::*};
+use crate::{chunk_loader::{ChunkLoader, ChunkStreamer}, camera::{CameraController, MovementConfig}, camera_path::{CameraPath, CameraPlayer, CameraRecorder}, render_context::RenderContext, path_tracing::{pt_render::PTRender, scene::Scene}, terrain::{NoiseTerrain, TerrainConfig}, texture::*};
+#[cfg(feature = "rasterization")]
+use crate::{camera::CameraBinding, objects::*};
 
 
 
@@ -33,52 +36,100 @@ use crate::{camera::{Camera, CameraController}, objects::*, path_tracing::{pt_re
 
 
 
+//Read at startup when they exist, so the landscape and the camera movement can be tuned without recompiling.
+const TERRAIN_CONFIG_PATH: &str = "terrain.ron";
+const MOVEMENT_CONFIG_PATH: &str = "movement.ron";
+
+//The default when the file doesn't exist, and also when it can't be loaded after saying why.
+fn load_config<T: Default>(path: &str, load: impl FnOnce(&str) -> std::io::Result<T>) -> T {
+    match load(path) {
+        Ok(config) => {
+            println!("Loaded {}", path);
+            config
+        }
+        Err(e) => {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                eprintln!("Failed to load {}, using the defaults: {}", path, e);
+            }
+            T::default()
+        }
+    }
+}
+
+const MAX_UPDATE_STEP: std::time::Duration = std::time::Duration::from_millis(100);
+
+pub const USAGE: &str = "Usage: ultimate-voxel-engine [--render-distance <chunks>]";
+
+pub struct WindowSettings {
+    pub render_distance: i32, //Chunks loaded around the camera in every direction.
+}
+
+impl Default for WindowSettings {
+    fn default() -> Self {
+        Self {
+            //The path tracer goes over every cube for every ray, the rasterizer can draw a lot more of them.
+            render_distance: if cfg!(feature = "rasterization") { 4 } else { 1 },
+        }
+    }
+}
+
+impl WindowSettings {
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut settings = Self::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("Missing value for {}", arg));
+            match arg.as_str() {
+                "--render-distance" => {
+                    let value = value()?;
+                    settings.render_distance = value.parse().map_err(|_| format!("Invalid value for {}: {}", arg, value))?;
+                }
+                _ => return Err(format!("Unknown argument {}", arg)),
+            }
+        }
+
+        if settings.render_distance < 0 {
+            return Err("--render-distance can't be negative".to_string());
+        }
+
+        Ok(settings)
+    }
+}
+
 pub struct State<'a> {
     surface: wgpu::Surface<'a>,
-    device: wgpu::Device,
-    queue: wgpu::Queue,
+    context: RenderContext,
     config: wgpu::SurfaceConfiguration,
     size: winit::dpi::PhysicalSize<u32>,
     window: &'a Window,
     clear_color: wgpu::Color,
     #[cfg(feature = "rasterization")] object_groups: Vec<ObjectGroup>,
-    camera: Camera,
+    #[cfg(feature = "rasterization")] camera_binding: CameraBinding,
     camera_controller: CameraController,
-    camera_controller_pt: TracingCameraController,
+    camera_recorder: Option<CameraRecorder>,
+    camera_player: Option<CameraPlayer>,
     depth_texture: Texture,
     pt_render: PTRender,
+    chunk_streamer: ChunkStreamer,
+    #[cfg(not(feature = "rasterization"))] accumulated_samples: u32, //Frames the camera has been standing still, these get averaged together.
+    #[cfg(not(feature = "rasterization"))] cubes_left_out: usize, //Cubes past the path tracer's MAX_CUBES at the last upload.
+    #[cfg(not(feature = "rasterization"))] over_software_limit: bool, //See PTRender::software_limit_warning.
     //instance_groups: Vec<InstanceGroup>,
 }
 
 impl <'a> State<'a > {
-    pub async fn new(window: &'a Window) -> State<'a> {
+    pub async fn new(window: &'a Window, settings: &WindowSettings) -> State<'a> {
         let size = window.inner_size();
 
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor { 
-            backends: wgpu::Backends::VULKAN,
-            ..Default::default()
-        });
+        let instance = RenderContext::instance();
 
         let surface = instance.create_surface(window).unwrap();
-        let adapter = instance.request_adapter(
-            &wgpu::RequestAdapterOptionsBase {
-                power_preference: wgpu::PowerPreference::HighPerformance,
-                force_fallback_adapter: false,
-                compatible_surface: Some(&surface)
-            }
-        ).await.unwrap();
+        let context = RenderContext::new(instance, Some(&surface), false).await.unwrap();
+        let device = &context.device;
+        let queue = &context.queue;
 
-        
-        let (device, queue) = adapter.request_device(
-            &wgpu::DeviceDescriptor {
-                required_features: wgpu::Features::empty(),
-                required_limits: wgpu::Limits::default(),
-                label: None,
-            },
-            None,
-        ).await.unwrap();
-
-        let surface_caps = surface.get_capabilities(&adapter);
+        let surface_caps = surface.get_capabilities(&context.adapter);
 
         let surface_format = surface_caps.formats.iter()
             .find(|f| f.is_srgb())
@@ -103,40 +154,60 @@ impl <'a> State<'a > {
             a: 1.0,
         };
 
-        let camera = Camera::new(&device, &config);
+        let depth_texture = Texture::create_depth_texture(device, [config.width, config.height], "depth_texture");
 
-        let mut object_groups: Vec<ObjectGroup> = Vec::new();
-        
-        object_groups.push(ObjectGroup::new(&device, &config, &camera));
-        
-        let camera_controller = CameraController::new(0.2f32);
+        //Both renderers build their chunks from the same generator, so they show the same world.
+        let terrain_config = load_config(TERRAIN_CONFIG_PATH, |path| TerrainConfig::load(path));
+        let terrain = NoiseTerrain::new(NoiseTerrain::DEFAULT_SEED, terrain_config);
 
-        let depth_texture = Texture::create_depth_texture(&device, &config, "depth_texture");
+        //The chunks around the camera are built in the background and added to the renderers in update, so the window shows up right away.
+        let chunk_loader = ChunkLoader::new(std::sync::Arc::new(terrain), ChunkLoader::default_workers());
+        let chunk_streamer = ChunkStreamer::new(chunk_loader, settings.render_distance);
 
-        let pt_render = PTRender::new(&device, &config, &queue);
-        let camera_controller_pt = TracingCameraController::new();
+        //pt_render owns the camera, the rasterizer gets its view-projection matrix from that same camera.
+        let scene = Scene::with_render_distance(settings.render_distance);
+        let mut pt_render = PTRender::with_scene(device, queue, config.format, [1920, 1080], scene);
+        //Frames get accumulated while the camera stands still, jittering them anti-aliases the image.
+        pt_render.set_jitter(queue, true);
+        let mut camera_controller = CameraController::new();
+        camera_controller.movement = load_config(MOVEMENT_CONFIG_PATH, |path| MovementConfig::load(path));
+
+        //The path tracer's image gets stretched over the window, the rasterizer draws straight to it and needs the window's aspect ratio.
+        #[cfg(feature = "rasterization")]
+        {
+            pt_render.camera.aspect_ratio = config.width as f32 / config.height as f32;
+        }
+        #[cfg(feature = "rasterization")]
+        let camera_binding = CameraBinding::new(device, &pt_render.camera);
+
+        #[cfg(feature = "rasterization")]
+        let object_groups: Vec<ObjectGroup> = vec![ObjectGroup::new(device, config.format, &camera_binding)];
 
         println!("Finished creating state");
 
         Self {
             surface,
-            device,
-            queue,
+            context,
             config,
             size,
             window,
             clear_color,
             #[cfg(feature = "rasterization")] object_groups,
-            camera,
+            #[cfg(feature = "rasterization")] camera_binding,
             camera_controller,
-            camera_controller_pt,
+            camera_recorder: None,
+            camera_player: None,
             depth_texture,
             pt_render,
+            chunk_streamer,
+            #[cfg(not(feature = "rasterization"))] accumulated_samples: 0,
+            #[cfg(not(feature = "rasterization"))] cubes_left_out: 0,
+            #[cfg(not(feature = "rasterization"))] over_software_limit: false,
         }
     }
 
     pub fn window(&self) -> &Window {
-        &self.window
+        self.window
     }
 
     pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
@@ -145,41 +216,260 @@ impl <'a> State<'a > {
         self.size = new_size;
         self.config.width = new_size.width;
         self.config.height = new_size.height;
-        self.surface.configure(&self.device, &self.config);
-        self.depth_texture = Texture::create_depth_texture(&self.device, &self.config, "depth_texture");
+        self.surface.configure(&self.context.device, &self.config);
+        self.depth_texture = Texture::create_depth_texture(&self.context.device, [self.config.width, self.config.height], "depth_texture");
+        #[cfg(feature = "rasterization")]
+        {
+            self.pt_render.camera.aspect_ratio = new_size.width as f32 / new_size.height as f32;
+            self.upload_camera();
+        }
         // println!("Resizing the screen");
     }
 
     pub fn input(&mut self, event: &WindowEvent) -> bool {
-        self.camera_controller.process_events(event);
-        self.camera_controller_pt.process_events(event)
+        if let WindowEvent::KeyboardInput {
+            event: KeyEvent {
+                state: ElementState::Pressed,
+                physical_key: PhysicalKey::Code(KeyCode::F3),
+                repeat: false,
+                ..
+            },
+            ..
+        } = event {
+            let debug_view = self.pt_render.debug_uniform.view().next();
+            self.pt_render.set_debug_view(&self.context.queue, debug_view);
+            #[cfg(not(feature = "rasterization"))]
+            {
+                self.accumulated_samples = 0;
+            }
+            println!("Debug view: {:?}", debug_view);
+            return true;
+        }
+
+        if let WindowEvent::KeyboardInput {
+            event: KeyEvent {
+                state: ElementState::Pressed,
+                physical_key: PhysicalKey::Code(KeyCode::F4),
+                repeat: false,
+                ..
+            },
+            ..
+        } = event {
+            self.pt_render.camera.projection = self.pt_render.camera.projection.next();
+            self.upload_camera();
+            println!("Projection: {:?}", self.pt_render.camera.projection);
+            return true;
+        }
+
+        if let WindowEvent::KeyboardInput {
+            event: KeyEvent {
+                state: ElementState::Pressed,
+                physical_key: PhysicalKey::Code(KeyCode::F2),
+                repeat: false,
+                ..
+            },
+            ..
+        } = event {
+            if let Err(e) = self.save_screenshot() {
+                eprintln!("Failed to save screenshot: {:?}", e);
+            }
+            return true;
+        }
+
+        if let WindowEvent::KeyboardInput {
+            event: KeyEvent {
+                state: ElementState::Pressed,
+                physical_key: PhysicalKey::Code(KeyCode::F5),
+                repeat: false,
+                ..
+            },
+            ..
+        } = event {
+            self.toggle_recording();
+            return true;
+        }
+
+        if let WindowEvent::KeyboardInput {
+            event: KeyEvent {
+                state: ElementState::Pressed,
+                physical_key: PhysicalKey::Code(KeyCode::F6),
+                repeat: false,
+                ..
+            },
+            ..
+        } = event {
+            self.toggle_playback();
+            return true;
+        }
+
+        self.camera_controller.process_events(event)
     }
 
     pub fn process_mouse(&mut self, delta_x: f32, delta_y: f32) {
-        self.camera_controller_pt.mouse_x_movement = delta_x;
-        self.camera_controller_pt.mouse_y_movement = delta_y;
+        self.camera_controller.mouse_x_movement = delta_x;
+        self.camera_controller.mouse_y_movement = delta_y;
     }
 
-    pub fn update(&mut self){
-        //TODO: I dont think this is very clean, probably want to write to the buffer inside of the camera controller.
+    //Uploads the camera to whichever renderer is in use.
+    fn upload_camera(&mut self) {
         #[cfg(feature = "rasterization")]
+        self.camera_binding.update(&self.context.queue, &self.pt_render.camera);
+        #[cfg(not(feature = "rasterization"))]
         {
-            self.camera_controller.update_camera(&mut self.camera.camera_inner);
-            self.camera.camera_uniform.update_view_proj(&self.camera.camera_inner);
-            self.queue.write_buffer(&self.camera.camera_buffer, 0, bytemuck::cast_slice(&[self.camera.camera_uniform]));
+            self.pt_render.update_camera_uniform(&self.context.queue);
+            self.accumulated_samples = 0;
         }
+    }
+
+    //Saves what the path tracer last rendered as both a png and a linear exr.
+    pub fn save_screenshot(&self) -> std::io::Result<()> {
+        let image = self.pt_render.capture(&self.context.device, &self.context.queue);
+
+        std::fs::create_dir_all("screenshots")?;
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let path = std::path::PathBuf::from(format!("screenshots/screenshot_{}", timestamp));
+
+        image.save_png(path.with_extension("png"))?;
+        image.save_exr(path.with_extension("exr"))?;
+        println!("Saved screenshot to {}", path.with_extension("png").display());
+        Ok(())
+    }
+
+    //Starts recording the camera, or stops and saves the recording to camera_paths/.
+    fn toggle_recording(&mut self) {
+        let Some(recorder) = self.camera_recorder.take() else {
+            self.camera_player = None;
+            self.camera_recorder = Some(CameraRecorder::new());
+            println!("Recording camera path");
+            return;
+        };
+
+        let path = recorder.finish(&self.pt_render.camera);
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let file = std::path::PathBuf::from(format!("camera_paths/path_{}.ron", timestamp));
+        match std::fs::create_dir_all("camera_paths").and_then(|_| path.save(&file)) {
+            Ok(()) => println!("Saved camera path of {:.1}s to {}", path.duration(), file.display()),
+            Err(e) => eprintln!("Failed to save camera path: {:?}", e),
+        }
+    }
+
+    //Plays back the newest camera path in camera_paths/, or stops the playback.
+    fn toggle_playback(&mut self) {
+        if self.camera_player.take().is_some() {
+            println!("Stopped camera path playback");
+            return;
+        }
+        if self.camera_recorder.is_some() {
+            self.toggle_recording();
+        }
+
+        let newest = std::fs::read_dir("camera_paths").into_iter().flatten()
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "ron"))
+            .max_by_key(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok());
+        let Some(file) = newest else {
+            eprintln!("No camera paths in camera_paths/ to play back, record one with F5");
+            return;
+        };
+
+        match CameraPath::load(&file) {
+            Ok(path) => {
+                println!("Playing back {}", file.display());
+                self.camera_player = Some(CameraPlayer::new(path));
+            }
+            Err(e) => eprintln!("Failed to load camera path {}: {:?}", file.display(), e),
+        }
+    }
+
+    //Unloads the chunks the camera moved away from and uploads the ones the workers finished since the last frame.
+    //The scene is kept up to date with the rasterizer as well, the autofocus needs it.
+    fn stream_chunks(&mut self) {
+        let update = self.chunk_streamer.update(self.pt_render.camera.origin);
+        for &position in &update.unloaded {
+            self.pt_render.scene.remove_chunk(position);
+            #[cfg(feature = "rasterization")]
+            self.object_groups[0].remove_chunk(position);
+        }
+        for chunk in &update.loaded {
+            self.pt_render.scene.add_chunk(chunk.position, &chunk.object);
+            #[cfg(feature = "rasterization")]
+            self.object_groups[0].add_chunk(&self.context.device, chunk.position, &chunk.mesh);
+        }
+
+        if !update.unloaded.is_empty() || !update.loaded.is_empty() {
+            #[cfg_attr(feature = "rasterization", allow(unused_variables))] //Only the path tracer draws from the cube buffer.
+            let left_out = self.pt_render.upload_scene(&self.context.queue);
+            #[cfg(not(feature = "rasterization"))]
+            {
+                self.accumulated_samples = 0;
+                //Only said when it starts and stops, not for every chunk that comes in.
+                if (left_out > 0) != (self.cubes_left_out > 0) {
+                    if left_out > 0 {
+                        eprintln!(
+                            "The scene has {} cubes more than the path tracer fits ({}), the chunks loaded last aren't drawn, lower --render-distance",
+                            left_out, crate::path_tracing::pt_render::MAX_CUBES,
+                        );
+                    } else {
+                        println!("All cubes fit in the path tracer again");
+                    }
+                }
+                self.cubes_left_out = left_out;
+
+                let warning = self.pt_render.software_limit_warning(&self.context);
+                if let (Some(warning), false) = (&warning, self.over_software_limit) {
+                    eprintln!("Warning: {}, lower --render-distance", warning);
+                }
+                self.over_software_limit = warning.is_some();
+            }
+        }
+    }
+
+    //dt is the time since the previous update, everything that moves is scaled by it.
+    pub fn update(&mut self, dt: std::time::Duration){
+        //After a stall (dragging the window, a breakpoint) the camera shouldn't jump across the world.
+        let dt = dt.min(MAX_UPDATE_STEP);
+        if let Some(player) = &mut self.camera_player {
+            //The path has the camera, anything done with the mouse in the meantime is dropped.
+            self.camera_controller.mouse_x_movement = 0.0;
+            self.camera_controller.mouse_y_movement = 0.0;
+            self.camera_controller.velocity = [0.0; 3];
+
+            if !player.update(&mut self.pt_render.camera, dt) {
+                let seconds = player.started.elapsed().as_secs_f32();
+                println!("Played back {} frames in {:.2}s, {:.1} fps", player.frames, seconds, player.frames as f32 / seconds);
+                self.camera_player = None;
+            }
+            self.upload_camera();
+        } else if self.camera_controller.update_camera(&mut self.pt_render.camera, dt, |camera| self.pt_render.scene.autofocus(camera)) {
+            self.upload_camera();
+        }
+
+        if let Some(recorder) = &mut self.camera_recorder {
+            recorder.update(&self.pt_render.camera, dt);
+        }
+
+        self.stream_chunks();
+
         #[cfg(not(feature = "rasterization"))]
         {
             // self.pt_render.camera.rotate_camera_pitch(0.01);
             // self.pt_render.camera.rotate_camera_yaw(0.01);
 
-            self.camera_controller_pt.update_camera(&self.queue, &mut self.pt_render);
+            //Keep averaging frames while the camera stands still, so the noise from depth of field converges.
+            self.pt_render.set_sample_index(&self.context.queue, self.accumulated_samples);
+            self.accumulated_samples = self.accumulated_samples.saturating_add(1);
 
             // self.pt_render.camera.rotate_camera_roll(0.01);
-            // self.pt_render.update_camera_uniform(&self.queue);
+            // self.pt_render.update_camera_uniform(&self.context.queue);
 
-            // self.pt_render.render_texture.update_texture(&self.queue, &self.pt_render.camera.render_scene_cpu(&self.pt_render.scene));
-            self.pt_render.render_scene_gpu(&self.device, &self.queue);
+            // self.pt_render.render_texture.update_texture(&self.context.queue, &self.pt_render.camera.render_scene_cpu(&self.pt_render.scene));
+            self.pt_render.render_scene_gpu(&self.context.device, &self.context.queue);
         }
     }
 
@@ -191,7 +481,7 @@ impl <'a> State<'a > {
 
         let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
 
-        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        let mut encoder = self.context.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
             label: Some("Render Encoder")
         });
 
@@ -222,14 +512,7 @@ impl <'a> State<'a > {
 
             //TODO: Render all the objects here...
             for object_group in &self.object_groups {
-                render_pass.set_pipeline(&object_group.render_pipeline);
-                render_pass.set_bind_group(0, &self.camera.camera_bind_group, &[]);
-                
-                for object in &object_group.objects {
-                    render_pass.set_vertex_buffer(0, object.vertex_buffer.slice(..));
-                    render_pass.set_index_buffer(object.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-                    render_pass.draw_indexed(0..object.num_vertices, 0, 0..1);
-                }
+                object_group.draw(&mut render_pass, &self.camera_binding);
             }
         }
 
@@ -260,7 +543,7 @@ impl <'a> State<'a > {
         
         
 
-        self.queue.submit(std::iter::once(encoder.finish()));
+        self.context.queue.submit(std::iter::once(encoder.finish()));
         output.present();
         // let elapsed = now.elapsed();
         // println!("Elapsed: {:.2?}", elapsed);
@@ -0,0 +1,64 @@
+use super::Material;
+
+//What a column of terrain looks like, picked from the temperature and humidity at that column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Biome {
+    Plains,
+    Desert,
+    Mountains,
+    Snow,
+}
+
+impl Biome {
+    //Materials of the top voxel and the ones under it, before the stone starts.
+    pub fn surface(self) -> (Material, Material) {
+        match self {
+            Biome::Plains => (Material::Grass, Material::Dirt),
+            Biome::Desert => (Material::Sand, Material::Sandstone),
+            Biome::Mountains => (Material::Stone, Material::Stone),
+            Biome::Snow => (Material::Snow, Material::Dirt),
+        }
+    }
+}
+
+//Where a biome sits on the climate map and the shape of its height profile.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct BiomeConfig {
+    pub biome: Biome,
+    pub temperature: f64, //Center of the biome on the climate map, both roughly -1..1.
+    pub humidity: f64,
+    pub base_height: f64, //Voxels added to the terrain's base_height.
+    pub amplitude: f64, //Multiplies the terrain's amplitude.
+    pub ridged: bool, //Ridged noise instead of the terrain's noise, for mountain ranges.
+}
+
+pub fn default_biomes() -> Vec<BiomeConfig> {
+    vec![
+        BiomeConfig { biome: Biome::Plains, temperature: 0.1, humidity: 0.3, base_height: 0.0, amplitude: 0.5, ridged: false },
+        BiomeConfig { biome: Biome::Desert, temperature: 0.45, humidity: -0.35, base_height: -1.0, amplitude: 0.4, ridged: false },
+        BiomeConfig { biome: Biome::Mountains, temperature: -0.15, humidity: -0.35, base_height: 10.0, amplitude: 3.0, ridged: true },
+        BiomeConfig { biome: Biome::Snow, temperature: -0.45, humidity: 0.25, base_height: 5.0, amplitude: 1.0, ridged: false },
+    ]
+}
+
+//How much every biome contributes to a column, summing to 1.
+//Biomes further than blend from the closest one on the climate map get nothing,
+//so away from the borders a column is a single biome and only the borders mix their heights.
+pub fn biome_weights(biomes: &[BiomeConfig], temperature: f64, humidity: f64, blend: f64) -> Vec<f64> {
+    let distances: Vec<f64> = biomes
+        .iter()
+        .map(|b| ((b.temperature - temperature).powi(2) + (b.humidity - humidity).powi(2)).sqrt())
+        .collect();
+    let closest = distances.iter().copied().fold(f64::INFINITY, f64::min);
+
+    let mut weights: Vec<f64> = distances.iter().map(|d| (blend - (d - closest)).max(0.0)).collect();
+    let total: f64 = weights.iter().sum();
+    if total > 0.0 {
+        weights.iter_mut().for_each(|w| *w /= total);
+    } else {
+        //Only happens with a blend of 0, then the closest biome takes it all.
+        let i = distances.iter().position(|&d| d == closest).unwrap();
+        weights[i] = 1.0;
+    }
+    weights
+}
@@ -0,0 +1,92 @@
+use noise::{MultiFractal, NoiseFn};
+
+use super::MIN_Z;
+
+//3D noise shaping the ground beyond what a height map can do: overhangs on the surface and caves below it.
+//Distances and scales are in voxels.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct CaveConfig {
+    pub overhang_strength: f64, //How far the surface gets pushed in and out, giving overhangs and arches. 0 turns it off.
+    pub overhang_scale: f64,
+    pub cheese_threshold: f64, //Cheese caves: big hollows where the noise is above this, 1 or more turns them off.
+    pub cheese_scale: f64,
+    pub cheese_roof: i32, //Ground cheese caves leave above them, so they don't riddle the surface.
+    pub tunnel_width: f64, //Worm caves: winding tunnels where two noises are both within this of 0, 0 turns them off.
+    pub tunnel_scale: f64,
+}
+
+impl Default for CaveConfig {
+    fn default() -> Self {
+        Self {
+            overhang_strength: 6.0,
+            overhang_scale: 12.0,
+            cheese_threshold: 0.55,
+            cheese_scale: 24.0,
+            cheese_roof: 8,
+            tunnel_width: 0.035,
+            tunnel_scale: 40.0,
+        }
+    }
+}
+
+pub struct Caves {
+    pub config: CaveConfig,
+    overhang_noise: noise::Perlin,
+    cheese_noise: noise::Fbm<noise::Perlin>,
+    tunnel_noise: [noise::Perlin; 2],
+}
+
+impl Caves {
+    pub fn new(seed: u32, config: CaveConfig) -> Self {
+        Self {
+            config,
+            //Seeds after the ones NoiseTerrain uses, so none of the noises line up.
+            overhang_noise: noise::Perlin::new(seed.wrapping_add(5)),
+            cheese_noise: noise::Fbm::<noise::Perlin>::new(seed.wrapping_add(6)).set_octaves(2),
+            tunnel_noise: [noise::Perlin::new(seed.wrapping_add(7)), noise::Perlin::new(seed.wrapping_add(8))],
+        }
+    }
+
+    fn scaled(point: [i32; 3], scale: f64) -> [f64; 3] {
+        point.map(|c| c as f64 / scale)
+    }
+
+    //Highest z there can be ground at in a column whose height map puts the top voxel at surface.
+    pub fn max_ground(&self, surface: i32) -> i32 {
+        surface + self.config.overhang_strength.max(0.0).ceil() as i32
+    }
+
+    //Whether the overhang noise leaves ground at point, before any caves are carved out of it.
+    //The bedrock is always there.
+    pub fn is_ground(&self, point: [i32; 3], surface: i32) -> bool {
+        if point[2] == MIN_Z || self.config.overhang_strength == 0.0 {
+            return point[2] <= surface;
+        }
+        let offset = self.overhang_noise.get(Self::scaled(point, self.config.overhang_scale)) * self.config.overhang_strength;
+        (point[2] - surface) as f64 <= offset
+    }
+
+    //Whether a cave hollows out the ground at point. Tunnels can break through the surface, cheese caves stay below their roof.
+    pub fn is_carved(&self, point: [i32; 3], surface: i32) -> bool {
+        if point[2] == MIN_Z {
+            return false;
+        }
+
+        if self.config.tunnel_width > 0.0 {
+            //Both noises are 0 on a surface each, the tunnels follow the lines where those cross.
+            //Squashed vertically, so the tunnels wind around more than they go up and down.
+            let mut tunnel_point = Self::scaled(point, self.config.tunnel_scale);
+            tunnel_point[2] *= 2.0;
+            if self.tunnel_noise[0].get(tunnel_point).abs() < self.config.tunnel_width
+                && self.tunnel_noise[1].get(tunnel_point).abs() < self.config.tunnel_width
+            {
+                return true;
+            }
+        }
+
+        self.config.cheese_threshold < 1.0
+            && point[2] <= surface - self.config.cheese_roof
+            && self.cheese_noise.get(Self::scaled(point, self.config.cheese_scale)) > self.config.cheese_threshold
+    }
+}
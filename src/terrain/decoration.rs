@@ -0,0 +1,201 @@
+use std::{io, path::Path};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::{biome::Biome, hash_coords, Material, Voxel};
+
+//Trees, cacti, boulders and prefabs placed on top of the terrain.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct DecorationConfig {
+    pub cell_size: i32, //The world is split into squares this wide, each gets at most one structure.
+    pub tree_chance: f64, //Chance that a square of grass or snow gets a tree.
+    pub cactus_chance: f64, //Like tree_chance, but for cacti in deserts.
+    pub boulder_chance: f64, //Like tree_chance, but for boulders anywhere on land.
+    pub prefabs: Vec<PrefabConfig>,
+}
+
+impl Default for DecorationConfig {
+    fn default() -> Self {
+        Self {
+            cell_size: 8,
+            tree_chance: 0.3,
+            cactus_chance: 0.1,
+            boulder_chance: 0.03,
+            prefabs: vec![],
+        }
+    }
+}
+
+//A user made voxel model scattered over the terrain like the trees.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct PrefabConfig {
+    pub path: String, //.ron file with the PrefabModel, relative to the terrain config.
+    pub chance: f64,
+    pub biomes: Vec<Biome>, //Biomes it can be placed in, empty allows all of them.
+    #[serde(skip)]
+    pub model: PrefabModel, //Loaded from path by TerrainConfig::load.
+}
+
+//Voxels relative to the spot the model stands on, [0, 0, 0] is the air right above the ground.
+//Written as rgba, so a model can have water or other see-through voxels in it.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PrefabModel {
+    pub voxels: Vec<([i32; 3], [u8; 4])>,
+}
+
+impl PrefabModel {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        crate::load_ron(path)
+    }
+
+    //Horizontal distance of the furthest voxel from the spot it stands on.
+    fn reach(&self) -> i32 {
+        self.voxels.iter().map(|(offset, _)| offset[0].abs().max(offset[1].abs())).max().unwrap_or(0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Structure {
+    Tree,
+    Cactus,
+    Boulder,
+    Prefab(usize),
+}
+
+pub struct Decorator {
+    pub config: DecorationConfig,
+    seed: u32,
+}
+
+impl Decorator {
+    //How far the built in structures reach out from their trunk.
+    const STRUCTURE_REACH: i32 = 3;
+
+    pub fn new(seed: u32, config: DecorationConfig) -> Self {
+        Self { config, seed }
+    }
+
+    //How far a structure can reach into the columns around the one it stands on.
+    pub fn reach(&self) -> i32 {
+        self.config.prefabs.iter().map(|prefab| prefab.model.reach()).fold(Self::STRUCTURE_REACH, i32::max)
+    }
+
+    //Voxels of every structure that can reach into the columns from min up to max, both included, at their world position.
+    //ground gives the top voxel and biome of a column, None where nothing can stand like under water.
+    //Every square has its own rng, so a structure comes out the same for every chunk it spills into.
+    pub fn place(&self, min: [i32; 2], max: [i32; 2], ground: impl Fn(i32, i32) -> Option<(i32, Option<Biome>)>) -> Vec<([i32; 3], Voxel)> {
+        let size = self.config.cell_size;
+        let reach = self.reach();
+        let cell_range = |axis: usize| (min[axis] - reach).div_euclid(size)..=(max[axis] + reach).div_euclid(size);
+
+        let mut voxels = vec![];
+        for cell_y in cell_range(1) {
+            for cell_x in cell_range(0) {
+                let mut rng = StdRng::seed_from_u64(hash_coords(self.seed.wrapping_add(11), &[cell_x, cell_y]));
+                let x = cell_x * size + rng.gen_range(0..size);
+                let y = cell_y * size + rng.gen_range(0..size);
+                let roll: f64 = rng.gen();
+
+                let Some((top, biome)) = ground(x, y) else { continue };
+                let Some(structure) = self.pick(biome, roll) else { continue };
+
+                voxels.extend(self.build(structure, &mut rng, [x, y, top + 1]));
+            }
+        }
+        voxels
+    }
+
+    //Every structure that fits the biome gets its chance in turn, roll picks one of them or none.
+    fn pick(&self, biome: Option<Biome>, roll: f64) -> Option<Structure> {
+        let config = &self.config;
+        let grassy = matches!(biome, None | Some(Biome::Plains) | Some(Biome::Snow));
+        let desert = biome == Some(Biome::Desert);
+
+        let built_in = [
+            (Structure::Tree, if grassy { config.tree_chance } else { 0.0 }),
+            (Structure::Cactus, if desert { config.cactus_chance } else { 0.0 }),
+            (Structure::Boulder, config.boulder_chance),
+        ];
+        let prefabs = config.prefabs.iter().enumerate().map(|(i, prefab)| {
+            let fits = prefab.biomes.is_empty() || biome.is_some_and(|b| prefab.biomes.contains(&b));
+            (Structure::Prefab(i), if fits { prefab.chance } else { 0.0 })
+        });
+
+        let mut total = 0.0;
+        for (structure, chance) in built_in.into_iter().chain(prefabs) {
+            total += chance;
+            if roll < total {
+                return Some(structure);
+            }
+        }
+        None
+    }
+
+    //Voxels of the structure standing on the air at anchor, at their world position.
+    //The rng picks the shape, the colors come from the world position like the rest of the terrain.
+    fn build(&self, structure: Structure, rng: &mut StdRng, anchor: [i32; 3]) -> Vec<([i32; 3], Voxel)> {
+        let mut voxels = vec![];
+        let at = |offset: [i32; 3]| [anchor[0] + offset[0], anchor[1] + offset[1], anchor[2] + offset[2]];
+        let mut put = |offset: [i32; 3], material: Material| {
+            let position = at(offset);
+            voxels.push((position, material.voxel(self.seed, position)));
+        };
+        match structure {
+            Structure::Tree => {
+                let height = rng.gen_range(4..=6);
+                for z in 0..height {
+                    put([0, 0, z], Material::Wood);
+                }
+                //A blob of leaves around the top of the trunk, without its corners.
+                let radius = 2;
+                for z in -1..=radius {
+                    for y in -radius..=radius {
+                        for x in -radius..=radius {
+                            if x * x + y * y + z * z <= radius * radius + 1 && !(x == 0 && y == 0 && z < 0) {
+                                put([x, y, height + z], Material::Leaves);
+                            }
+                        }
+                    }
+                }
+            }
+            Structure::Cactus => {
+                let height = rng.gen_range(2..=4);
+                for z in 0..height {
+                    put([0, 0, z], Material::Cactus);
+                }
+                if height > 2 && rng.gen_bool(0.5) {
+                    let side = if rng.gen_bool(0.5) { 1 } else { -1 };
+                    put([side, 0, 1], Material::Cactus);
+                    put([side, 0, 2], Material::Cactus);
+                }
+            }
+            Structure::Boulder => {
+                //Sunk halfway into the ground, only the part sticking out into the air ends up in the chunk.
+                let radius: i32 = rng.gen_range(1..=2);
+                for z in -radius..=radius {
+                    for y in -radius..=radius {
+                        for x in -radius..=radius {
+                            if x * x + y * y + z * z <= radius * radius {
+                                put([x, y, z], Material::Stone);
+                            }
+                        }
+                    }
+                }
+            }
+            Structure::Prefab(i) => {
+                //Turned a random number of quarter turns, so the copies don't all face the same way.
+                let turns = rng.gen_range(0..4);
+                return self.config.prefabs[i].model.voxels.iter().map(|&(offset, color)| {
+                    let [mut x, mut y, z] = offset;
+                    for _ in 0..turns {
+                        (x, y) = (-y, x);
+                    }
+                    (at([x, y, z]), Voxel { color })
+                }).collect();
+            }
+        }
+        voxels
+    }
+}
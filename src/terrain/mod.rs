@@ -0,0 +1,512 @@
+use noise::{MultiFractal, NoiseFn};
+pub mod biome;
+pub mod caves;
+pub mod decoration;
+pub mod water;
+
+use biome::{Biome, BiomeConfig};
+use caves::{CaveConfig, Caves};
+use decoration::{DecorationConfig, Decorator, PrefabModel};
+use water::{Lake, Water, WaterConfig};
+
+//Width and depth of a chunk in voxels, the same for every renderer.
+pub const CHUNK_SIZE: i32 = 64;
+//Chunks are columns reaching from the bedrock at MIN_Z up to MIN_Z + CHUNK_HEIGHT.
+pub const CHUNK_HEIGHT: i32 = 128;
+pub const MIN_Z: i32 = -64;
+
+//Every chunk also stores a ring of this many voxels from its neighbours,
+//so it can tell which of its own voxels touch air without the neighbouring chunks being loaded.
+const APRON: i32 = 1;
+const STRIDE: i32 = CHUNK_SIZE + 2 * APRON;
+
+//A single voxel, stored as its rgba8 color. Fully transparent means there is nothing there,
+//partly transparent voxels like water can be seen through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Voxel {
+    pub color: [u8; 4],
+}
+
+impl Voxel {
+    pub const AIR: Voxel = Voxel { color: [0; 4] };
+
+    pub fn from_rgb(rgb: [u8; 3]) -> Self {
+        Self { color: [rgb[0], rgb[1], rgb[2], 255] }
+    }
+
+    pub fn is_air(&self) -> bool {
+        self.color[3] == 0
+    }
+
+    pub fn is_opaque(&self) -> bool {
+        self.color[3] == 255
+    }
+
+    pub fn color_f32(&self) -> [f32; 4] {
+        self.color.map(|c| c as f32 / 255.0)
+    }
+}
+
+//What the terrain is made of, each has its own color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Material {
+    Bedrock,
+    Stone,
+    Dirt,
+    Grass,
+    Sand,
+    Sandstone,
+    Snow,
+    Water,
+    Wood,
+    Leaves,
+    Cactus,
+}
+
+impl Material {
+    pub fn base_color(self) -> [u8; 3] {
+        match self {
+            Material::Bedrock => [45, 42, 48],
+            Material::Stone => [125, 125, 130],
+            Material::Dirt => [115, 80, 50],
+            Material::Grass => [85, 150, 55],
+            Material::Sand => [210, 195, 140],
+            Material::Sandstone => [190, 150, 95],
+            Material::Snow => [235, 240, 245],
+            Material::Water => [40, 95, 175],
+            Material::Wood => [100, 70, 40],
+            Material::Leaves => [50, 120, 45],
+            Material::Cactus => [70, 140, 60],
+        }
+    }
+
+    pub fn alpha(self) -> u8 {
+        match self {
+            Material::Water => 160,
+            _ => 255,
+        }
+    }
+
+    //The voxel of this material at a world position. Every voxel gets a slightly different shade so single voxels
+    //can still be told apart, picked by hashing its position so regenerating a chunk gives the exact same colors.
+    //Water stays flat, shades would make its surface look bumpy.
+    pub fn voxel(self, seed: u32, position: [i32; 3]) -> Voxel {
+        let shade = if self == Material::Water {
+            1.0
+        } else {
+            0.8 + 0.2 * (hash_coords(seed, &position) >> 40) as f32 / (1 << 24) as f32
+        };
+        let [r, g, b] = self.base_color().map(|c| (c as f32 * shade) as u8);
+        Voxel { color: [r, g, b, self.alpha()] }
+    }
+}
+
+//Material of ground at height z with depth voxels of ground above it before the air starts:
+//top (like grass) on top, dirt_depth - 1 voxels of under (like dirt) below that and then stone down to the bedrock.
+pub fn layered_material(z: i32, depth: i32, dirt_depth: i32, top: Material, under: Material) -> Material {
+    if z == MIN_Z {
+        Material::Bedrock
+    } else if depth == 0 {
+        top
+    } else if depth < dirt_depth {
+        under
+    } else {
+        Material::Stone
+    }
+}
+
+//The voxels of one chunk column, CHUNK_SIZE wide and deep and CHUNK_HEIGHT high, plus the apron around it.
+//This is what the renderers build their own representation from, the path tracer cubes and the rasterizer a mesh.
+//Local coordinates go from -APRON to CHUNK_SIZE + APRON horizontally and from 0 to CHUNK_HEIGHT vertically.
+pub struct VoxelChunk {
+    pub chunk_x: i32,
+    pub chunk_y: i32,
+    pub voxels: Vec<Voxel>, //x changes fastest, then y, then z.
+}
+
+impl VoxelChunk {
+    pub fn new(chunk_x: i32, chunk_y: i32) -> Self {
+        Self {
+            chunk_x,
+            chunk_y,
+            voxels: vec![Voxel::AIR; (STRIDE * STRIDE * CHUNK_HEIGHT) as usize],
+        }
+    }
+
+    //World position of the voxel at local [0, 0, 0].
+    pub fn origin(&self) -> [i32; 3] {
+        [self.chunk_x * CHUNK_SIZE, self.chunk_y * CHUNK_SIZE, MIN_Z]
+    }
+
+    //Horizontal local coordinates the generator has to fill, the chunk itself and its apron.
+    pub fn filled_range() -> std::ops::Range<i32> {
+        -APRON..CHUNK_SIZE + APRON
+    }
+
+    fn index(local: [i32; 3]) -> Option<usize> {
+        let horizontal = Self::filled_range();
+        if horizontal.contains(&local[0]) && horizontal.contains(&local[1]) && (0..CHUNK_HEIGHT).contains(&local[2]) {
+            Some((local[0] + APRON + STRIDE * (local[1] + APRON + STRIDE * local[2])) as usize)
+        } else {
+            None
+        }
+    }
+
+    pub fn contains(local: [i32; 3]) -> bool {
+        Self::index(local).is_some()
+    }
+
+    //Anything outside of the chunk and its apron is air.
+    pub fn get(&self, local: [i32; 3]) -> Voxel {
+        Self::index(local).map_or(Voxel::AIR, |i| self.voxels[i])
+    }
+
+    pub fn set(&mut self, local: [i32; 3], voxel: Voxel) {
+        let i = Self::index(local).expect("Voxel is outside of the chunk");
+        self.voxels[i] = voxel;
+    }
+
+    //Every voxel of the chunk (not the apron) that isn't air, with its local position.
+    fn solid_local(&self) -> impl Iterator<Item = ([i32; 3], Voxel)> + '_ {
+        (0..CHUNK_HEIGHT).flat_map(move |z| {
+            (0..CHUNK_SIZE).flat_map(move |y| (0..CHUNK_SIZE).map(move |x| [x, y, z]))
+        })
+        .map(|local| (local, self.get(local)))
+        .filter(|(_, voxel)| !voxel.is_air())
+    }
+
+    fn to_world(&self, local: [i32; 3]) -> [i32; 3] {
+        let origin = self.origin();
+        [origin[0] + local[0], origin[1] + local[1], origin[2] + local[2]]
+    }
+
+    //Every voxel of the chunk that isn't air, with its world position.
+    pub fn solid_voxels(&self) -> impl Iterator<Item = ([i32; 3], Voxel)> + '_ {
+        self.solid_local().map(|(local, voxel)| (self.to_world(local), voxel))
+    }
+
+    //Whether the voxel at local is solid with at least one side that can be seen, the only ones that need rendering.
+    //Sides touching air can always be seen, opaque voxels can also be seen through water. Water next to water can't,
+    //so only the surface of a lake is rendered and not all of it.
+    //Below the bedrock counts as solid, so the bottom of the world is never shown.
+    pub fn is_visible(&self, local: [i32; 3]) -> bool {
+        const SIDES: [[i32; 3]; 6] = [[1, 0, 0], [-1, 0, 0], [0, 1, 0], [0, -1, 0], [0, 0, 1], [0, 0, -1]];
+        let voxel = self.get(local);
+        !voxel.is_air() && SIDES.iter().any(|side| {
+            let neighbour = [local[0] + side[0], local[1] + side[1], local[2] + side[2]];
+            if neighbour[2] < 0 {
+                return false;
+            }
+            let neighbour = self.get(neighbour);
+            neighbour.is_air() || (voxel.is_opaque() && !neighbour.is_opaque())
+        })
+    }
+
+    //Every visible voxel of the chunk with its world position, including the walls of caves.
+    pub fn visible_voxels(&self) -> impl Iterator<Item = ([i32; 3], Voxel)> + '_ {
+        self.solid_local()
+            .filter(|(local, _)| self.is_visible(*local))
+            .map(|(local, voxel)| (self.to_world(local), voxel))
+    }
+}
+
+//Fills chunks with terrain. The same seed and chunk always give the same voxels,
+//so chunks can be generated in any order and both renderers show the same world.
+pub trait TerrainGenerator: Send + Sync {
+    fn seed(&self) -> u32;
+    fn generate_chunk(&self, chunk_x: i32, chunk_y: i32) -> VoxelChunk;
+}
+
+//Which noise the height of the surface comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum NoiseType {
+    Perlin, //A single octave, smooth rolling hills. Ignores octaves, lacunarity and persistence.
+    Fbm, //Octaves of perlin noise added together, hills with smaller bumps on them.
+    RidgedMulti, //Sharp ridges and valleys, for mountain ranges.
+}
+
+//Everything that shapes the landscape, stored as .ron so it can be tuned without recompiling.
+//Missing fields fall back to their defaults.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct TerrainConfig {
+    pub noise: NoiseType,
+    pub octaves: usize,
+    pub lacunarity: f64, //How much the frequency goes up with every octave.
+    pub persistence: f64, //How much the amplitude goes down with every octave.
+    pub scale: f64, //Voxels per unit of noise, higher stretches the hills out.
+    pub amplitude: f64, //Voxels the surface goes above and below base_height.
+    pub base_height: f64,
+    pub sea_level: i32, //Air below this is filled with water. Ground under water is covered in sand, whatever its biome.
+    pub warp_strength: f64, //Voxels the noise lookups get pushed around by domain warping, 0 turns it off.
+    pub warp_scale: f64, //Like scale, but for the warping.
+    pub dirt_depth: i32, //Voxels of dirt under the grass, including the grass.
+    pub climate_scale: f64, //Like scale, but for the temperature and humidity that pick the biomes.
+    pub biome_blend: f64, //Distance on the climate map over which neighbouring biomes blend their heights.
+    pub biomes: Vec<BiomeConfig>, //Empty gives a single landscape of grass and dirt.
+    pub caves: CaveConfig,
+    pub water: WaterConfig,
+    pub decoration: DecorationConfig,
+}
+
+impl Default for TerrainConfig {
+    fn default() -> Self {
+        Self {
+            noise: NoiseType::Fbm,
+            octaves: 4,
+            lacunarity: 2.0,
+            persistence: 0.5,
+            scale: 48.0,
+            amplitude: 8.0,
+            base_height: 2.0,
+            sea_level: -2,
+            warp_strength: 12.0,
+            warp_scale: 64.0,
+            dirt_depth: 4,
+            climate_scale: 384.0,
+            biome_blend: 0.15,
+            biomes: biome::default_biomes(),
+            caves: CaveConfig::default(),
+            water: WaterConfig::default(),
+            decoration: DecorationConfig::default(),
+        }
+    }
+}
+
+impl TerrainConfig {
+    //Also loads the models of the prefabs, their paths are relative to the config file.
+    pub fn load(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        let path = path.as_ref();
+        let mut config: Self = crate::load_ron(path)?;
+        //Caught here instead of as a panic in the middle of generating a chunk.
+        let water = &config.water;
+        if water.lake_spacing <= 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "water.lake_spacing has to be above 0"));
+        }
+        let [smallest, largest] = water.lake_radius;
+        if smallest.is_nan() || largest.is_nan() || smallest > largest {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "water.lake_radius has to be [smallest, largest]"));
+        }
+        if config.decoration.cell_size <= 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "decoration.cell_size has to be above 0"));
+        }
+        let directory = path.parent().unwrap_or(std::path::Path::new(""));
+        for prefab in &mut config.decoration.prefabs {
+            prefab.model = PrefabModel::load(directory.join(&prefab.path))?;
+        }
+        Ok(config)
+    }
+}
+
+//Terrain from fractal noise as configured by a TerrainConfig, filled from the bedrock up.
+pub struct NoiseTerrain {
+    pub seed: u32,
+    pub config: TerrainConfig,
+    height_noise: Box<dyn NoiseFn<f64, 2> + Send + Sync>,
+    ridged_noise: noise::RidgedMulti<noise::Perlin>,
+    warp_noise: [noise::Perlin; 2],
+    climate_noise: [noise::Perlin; 2], //Temperature and humidity.
+    caves: Caves,
+    water: Water,
+    decorator: Decorator,
+}
+
+impl Default for NoiseTerrain {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_SEED, TerrainConfig::default())
+    }
+}
+
+impl NoiseTerrain {
+    pub const DEFAULT_SEED: u32 = 1;
+
+    pub fn new(seed: u32, config: TerrainConfig) -> Self {
+        let frequency = 1.0 / config.scale;
+        let height_noise: Box<dyn NoiseFn<f64, 2> + Send + Sync> = match config.noise {
+            NoiseType::Perlin => Box::new(noise::ScalePoint::new(noise::Perlin::new(seed)).set_scale(frequency)),
+            NoiseType::Fbm => Box::new(
+                noise::Fbm::<noise::Perlin>::new(seed)
+                    .set_octaves(config.octaves)
+                    .set_frequency(frequency)
+                    .set_lacunarity(config.lacunarity)
+                    .set_persistence(config.persistence),
+            ),
+            NoiseType::RidgedMulti => Box::new(
+                noise::RidgedMulti::<noise::Perlin>::new(seed)
+                    .set_octaves(config.octaves)
+                    .set_frequency(frequency)
+                    .set_lacunarity(config.lacunarity)
+                    .set_persistence(config.persistence),
+            ),
+        };
+
+        let ridged_noise = noise::RidgedMulti::<noise::Perlin>::new(seed)
+            .set_octaves(config.octaves)
+            .set_frequency(frequency)
+            .set_lacunarity(config.lacunarity)
+            .set_persistence(config.persistence);
+
+        Self {
+            seed,
+            caves: Caves::new(seed, config.caves),
+            water: Water::new(seed, config.water),
+            decorator: Decorator::new(seed, config.decoration.clone()),
+            config,
+            height_noise,
+            ridged_noise,
+            //Different seeds than the height noise, otherwise the warp would follow the hills.
+            warp_noise: [noise::Perlin::new(seed.wrapping_add(1)), noise::Perlin::new(seed.wrapping_add(2))],
+            climate_noise: [noise::Perlin::new(seed.wrapping_add(3)), noise::Perlin::new(seed.wrapping_add(4))],
+        }
+    }
+
+    pub fn with_seed(seed: u32) -> Self {
+        Self::new(seed, TerrainConfig::default())
+    }
+
+    //z of the top voxel of the column at x, y.
+    pub fn height_at(&self, x: i32, y: i32) -> i32 {
+        self.column_at(x, y).0
+    }
+
+    //Biome with the most weight at the column, None without any biomes configured.
+    pub fn biome_at(&self, x: i32, y: i32) -> Option<Biome> {
+        self.column_at(x, y).1
+    }
+
+    //Height and biome of the column at x, y.
+    pub fn column_at(&self, x: i32, y: i32) -> (i32, Option<Biome>) {
+        let mut point = [x as f64, y as f64];
+
+        //Domain warping: looking the height up a bit off to the side bends straight slopes into curvy ones.
+        if self.config.warp_strength != 0.0 {
+            let warp_point = [point[0] / self.config.warp_scale, point[1] / self.config.warp_scale];
+            point[0] += self.warp_noise[0].get(warp_point) * self.config.warp_strength;
+            point[1] += self.warp_noise[1].get(warp_point) * self.config.warp_strength;
+        }
+
+        let biomes = &self.config.biomes;
+        if biomes.is_empty() {
+            let noise = self.height_noise.get(point);
+            return ((self.config.base_height + noise * self.config.amplitude).floor() as i32, None);
+        }
+
+        //Looked up at the warped point as well, so the borders between biomes aren't smooth blobs.
+        let climate_point = [point[0] / self.config.climate_scale, point[1] / self.config.climate_scale];
+        let temperature = self.climate_noise[0].get(climate_point);
+        let humidity = self.climate_noise[1].get(climate_point);
+        let weights = biome::biome_weights(biomes, temperature, humidity, self.config.biome_blend);
+
+        //Every biome's height function is evaluated where it has any weight and the results are mixed,
+        //so the ground goes smoothly from one profile to the next instead of stepping at the border.
+        let (mut hills, mut ridges) = (None, None);
+        let mut height = self.config.base_height;
+        let mut dominant = 0;
+        for (i, (biome, &weight)) in biomes.iter().zip(&weights).enumerate() {
+            if weight > weights[dominant] {
+                dominant = i;
+            }
+            if weight == 0.0 {
+                continue;
+            }
+            let noise = if biome.ridged {
+                *ridges.get_or_insert_with(|| self.ridged_noise.get(point))
+            } else {
+                *hills.get_or_insert_with(|| self.height_noise.get(point))
+            };
+            height += weight * (biome.base_height + biome.amplitude * self.config.amplitude * noise);
+        }
+
+        (height.floor() as i32, Some(biomes[dominant].biome))
+    }
+
+    //Surface, top of the water and biome of the column at x, y once rivers and lakes are carved into it,
+    //clamped to the height of a chunk. lakes has to have every lake that reaches the column.
+    fn carved_column(&self, x: i32, y: i32, lakes: &[Lake]) -> (i32, i32, Option<Biome>) {
+        let (surface, biome) = self.column_at(x, y);
+        let (surface, water_top) = self.water.carve(x, y, surface, lakes, self.config.sea_level);
+        (surface.clamp(MIN_Z, MIN_Z + CHUNK_HEIGHT - 1), water_top.min(MIN_Z + CHUNK_HEIGHT - 1), biome)
+    }
+
+    //Top voxel of the column at x, y with its biome, for structures to stand on. None when it is under water.
+    fn decoration_ground(&self, x: i32, y: i32, lakes: &[Lake]) -> Option<(i32, Option<Biome>)> {
+        let (surface, water_top, biome) = self.carved_column(x, y, lakes);
+        let top = (MIN_Z..=self.caves.max_ground(surface).min(MIN_Z + CHUNK_HEIGHT - 1)).rev().find(|&z| {
+            let point = [x, y, z];
+            self.caves.is_ground(point, surface) && !self.caves.is_carved(point, surface)
+        })?;
+        (top > water_top).then_some((top, biome))
+    }
+}
+
+impl TerrainGenerator for NoiseTerrain {
+    fn seed(&self) -> u32 {
+        self.seed
+    }
+
+    fn generate_chunk(&self, chunk_x: i32, chunk_y: i32) -> VoxelChunk {
+        let mut chunk = VoxelChunk::new(chunk_x, chunk_y);
+        let origin = chunk.origin();
+        let range = VoxelChunk::filled_range();
+        let min = [origin[0] + range.start, origin[1] + range.start];
+        let max = [origin[0] + range.end - 1, origin[1] + range.end - 1];
+        //Also the lakes around the structures that reach into this chunk, they need to know where they stand.
+        let reach = self.decorator.reach();
+        let lakes = self.water.lakes_near([min[0] - reach, min[1] - reach], [max[0] + reach, max[1] + reach], |x, y| self.height_at(x, y));
+
+        for y in VoxelChunk::filled_range() {
+            for x in VoxelChunk::filled_range() {
+                let (surface, water_top, biome) = self.carved_column(origin[0] + x, origin[1] + y, &lakes);
+                let (top, under) = if surface <= water_top {
+                    (Material::Sand, Material::Sand)
+                } else {
+                    biome.map_or((Material::Grass, Material::Dirt), Biome::surface)
+                };
+
+                //Walks down from the highest the overhangs can reach, counting the ground since the last air,
+                //so the underside of an overhang is stone instead of grass.
+                //Caves are carved after the layers are picked, their floors are bare rock.
+                //Water fills the open air below water_top, but not the caves.
+                let mut depth = 0;
+                let highest = self.caves.max_ground(surface).max(water_top).min(MIN_Z + CHUNK_HEIGHT - 1);
+                for z in (MIN_Z..=highest).rev() {
+                    let point = [origin[0] + x, origin[1] + y, z];
+                    if !self.caves.is_ground(point, surface) {
+                        depth = 0;
+                        if z <= water_top {
+                            chunk.set([x, y, z - MIN_Z], Material::Water.voxel(self.seed, point));
+                        }
+                        continue;
+                    }
+                    let material = layered_material(z, depth, self.config.dirt_depth, top, under);
+                    depth += 1;
+                    if !self.caves.is_carved(point, surface) {
+                        chunk.set([x, y, z - MIN_Z], material.voxel(self.seed, point));
+                    }
+                }
+            }
+        }
+
+        //Structures only go into air, so they never cut into the ground. Where two overlap the one placed first wins,
+        //they are always placed in the same order so that is the same in every chunk.
+        for (position, voxel) in self.decorator.place(min, max, |x, y| self.decoration_ground(x, y, &lakes)) {
+            let local = [position[0] - origin[0], position[1] - origin[1], position[2] - MIN_Z];
+            if VoxelChunk::contains(local) && chunk.get(local).is_air() {
+                chunk.set(local, voxel);
+            }
+        }
+
+        chunk
+    }
+}
+
+//Mixes coordinates into the world seed, neighbouring coordinates get unrelated hashes.
+//Used to seed the rngs of chunks and squares and to pick the shades of voxels, so nothing depends on generation order.
+pub fn hash_coords(seed: u32, coords: &[i32]) -> u64 {
+    let mut h = seed as u64;
+    for &c in coords {
+        h = (h ^ c as u32 as u64).wrapping_mul(0x9e3779b97f4a7c15);
+        h ^= h >> 32;
+    }
+    h
+}
@@ -0,0 +1,132 @@
+use noise::NoiseFn;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::hash_coords;
+
+//Rivers and lakes, distances and scales are in voxels. The sea comes from TerrainConfig's sea_level.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct WaterConfig {
+    pub river_width: f64, //Rivers run where the river noise is within this of 0, 0 turns them off.
+    pub valley_width: f64, //Like river_width, for the slopes going down to the river.
+    pub river_scale: f64,
+    pub river_depth: i32, //Voxels the river bed lies below sea level, the sea flows into it.
+    pub lake_spacing: i32, //Every lake_spacing by lake_spacing square of the world can have a lake in it.
+    pub lake_chance: f64, //Chance that a square has a lake, 0 turns them off.
+    pub lake_radius: [f64; 2], //Smallest and largest radius.
+    pub lake_depth: f64, //Voxels the center of a lake goes below its water.
+}
+
+impl Default for WaterConfig {
+    fn default() -> Self {
+        Self {
+            river_width: 0.02,
+            valley_width: 0.08,
+            river_scale: 384.0,
+            river_depth: 3,
+            lake_spacing: 192,
+            lake_chance: 0.5,
+            lake_radius: [10.0, 24.0],
+            lake_depth: 5.0,
+        }
+    }
+}
+
+//A round lake, its water reaches up to level.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Lake {
+    pub center: [f64; 2],
+    pub radius: f64,
+    pub level: i32,
+}
+
+pub struct Water {
+    pub config: WaterConfig,
+    seed: u32,
+    river_noise: noise::Perlin,
+}
+
+impl Water {
+    //Points around the shore of a lake its level is picked from.
+    pub const SHORE_SAMPLES: usize = 12;
+
+    pub fn new(seed: u32, config: WaterConfig) -> Self {
+        Self {
+            config,
+            seed,
+            river_noise: noise::Perlin::new(seed.wrapping_add(9)),
+        }
+    }
+
+    //Lakes that can reach into the columns from min up to max, both included.
+    //height_at is the terrain before any water is carved into it. A lake's level is just below the lowest point of its shore,
+    //so it doesn't spill out of its basin.
+    pub fn lakes_near(&self, min: [i32; 2], max: [i32; 2], height_at: impl Fn(i32, i32) -> i32) -> Vec<Lake> {
+        let config = &self.config;
+        if config.lake_chance <= 0.0 {
+            return vec![];
+        }
+
+        let spacing = config.lake_spacing;
+        let reach = config.lake_radius[1].ceil() as i32;
+        let cell_range = |axis: usize| (min[axis] - reach).div_euclid(spacing)..=(max[axis] + reach).div_euclid(spacing);
+
+        let mut lakes = vec![];
+        for cell_y in cell_range(1) {
+            for cell_x in cell_range(0) {
+                //Seeded per square, so a lake is the same no matter which chunk asks for it.
+                let mut rng = StdRng::seed_from_u64(hash_coords(self.seed.wrapping_add(10), &[cell_x, cell_y]));
+                if rng.gen::<f64>() >= config.lake_chance {
+                    continue;
+                }
+
+                let radius = rng.gen_range(config.lake_radius[0]..=config.lake_radius[1]);
+                //Kept far enough inside the square that neighbouring lakes never overlap.
+                let margin = radius.min(spacing as f64 / 2.0);
+                let center = [
+                    (cell_x * spacing) as f64 + rng.gen_range(margin..=spacing as f64 - margin),
+                    (cell_y * spacing) as f64 + rng.gen_range(margin..=spacing as f64 - margin),
+                ];
+
+                let shore = (0..Self::SHORE_SAMPLES)
+                    .map(|i| {
+                        let angle = i as f64 / Self::SHORE_SAMPLES as f64 * std::f64::consts::TAU;
+                        height_at((center[0] + radius * angle.cos()) as i32, (center[1] + radius * angle.sin()) as i32)
+                    })
+                    .min()
+                    .unwrap();
+                lakes.push(Lake { center, radius, level: shore - 1 });
+            }
+        }
+        lakes
+    }
+
+    //Surface of the column at x, y after the rivers and lakes are carved into it, and the highest z water fills up to.
+    pub fn carve(&self, x: i32, y: i32, surface: i32, lakes: &[Lake], sea_level: i32) -> (i32, i32) {
+        let config = &self.config;
+        let mut surface = surface;
+        let mut water_top = sea_level - 1;
+
+        //Rivers are valleys cut below sea level, the sea fills them the same way it fills everything else.
+        if config.river_width > 0.0 {
+            let distance = self.river_noise.get([x as f64 / config.river_scale, y as f64 / config.river_scale]).abs();
+            if distance < config.valley_width {
+                let bed = (sea_level - 1 - config.river_depth) as f64;
+                let t = ((distance - config.river_width) / (config.valley_width - config.river_width)).max(0.0);
+                let slope = t * t * (3.0 - 2.0 * t);
+                surface = surface.min((bed + (surface as f64 - bed) * slope).floor() as i32);
+            }
+        }
+
+        for lake in lakes {
+            let distance = ((x as f64 - lake.center[0]).powi(2) + (y as f64 - lake.center[1]).powi(2)).sqrt() / lake.radius;
+            if distance < 1.0 {
+                let bed = lake.level as f64 - config.lake_depth * (1.0 - distance * distance);
+                surface = surface.min(bed.floor() as i32);
+                water_top = water_top.max(lake.level);
+            }
+        }
+
+        (surface, water_top)
+    }
+}
@@ -1,4 +1,3 @@
-use wgpu::ImageCopyTextureBase;
 
 use crate::path_tracing::render_image::RenderImage;
 
@@ -14,12 +13,12 @@ impl Texture {
 
     pub fn create_depth_texture(
         device: &wgpu::Device,
-        config: &wgpu::SurfaceConfiguration,
+        size: [u32; 2],
         label: &str
     ) -> Self {
         let size = wgpu::Extent3d {
-            width: config.width,
-            height: config.height,
+            width: size[0],
+            height: size[1],
             depth_or_array_layers: 1,
         };
 
@@ -0,0 +1,493 @@
+//Golden image tests: fixed scenes rendered from fixed camera poses, compared against the pngs in tests/golden.
+//The path tracer is always checked through the cpu tracer, the gpu renderers only when a software adapter is available.
+//Without one the gpu tests print that they were skipped, with REQUIRE_GPU=1 they fail instead.
+//
+//After an intended change to the output, regenerate the references with:
+//    UPDATE_GOLDEN=1 cargo test --test golden_images
+//On a mismatch the actual image and a diff image are written to target/tmp/golden-diff.
+
+use std::{fs::File, io::{BufWriter, Write}, path::PathBuf};
+
+use ultimate_voxel_engine::{
+    camera::{Camera, CameraBinding, CameraPose, Projection},
+    camera_path::{CameraKeyframe, CameraPath},
+    objects::{Object, ObjectGroup},
+    path_tracing::{
+        chunk::CHUNK_SIZE,
+        cpu_tracer::CpuTracer,
+        debug_view::{DebugUniform, DebugView},
+        pt_render::{ComputeParams, PTRender},
+        render_image::RenderImage,
+        scene::Scene,
+    },
+    render_context::RenderContext,
+    terrain::{caves::CaveConfig, water::WaterConfig, NoiseTerrain, TerrainConfig, TerrainGenerator},
+    texture::Texture,
+};
+
+const SEED: u32 = 31;
+const SIZE: [usize; 2] = [128, 72];
+
+//CIE76 color difference above which a pixel counts as different, ~2.3 is just noticeable.
+const MAX_DELTA_E: f32 = 5.0;
+//Fraction of pixels allowed to differ, so single edge pixels from different rasterizers don't fail the test.
+const MAX_DIFFERING_FRACTION: f32 = 0.005;
+
+fn poses() -> [(&'static str, CameraPose); 2] {
+    [
+        ("overview", CameraPose { origin: [-10.0, -10.0, 20.0], looking_at: [32.0, 32.0, 0.0], fov_y: 40.0, ..Default::default() }),
+        ("close_up", CameraPose { origin: [20.0, 8.0, 14.0], looking_at: [28.0, 24.0, 8.0], fov_y: 60.0, ..Default::default() }),
+    ]
+}
+
+//Focused on the voxel in the center with a wide aperture, so both the fore- and background are blurred.
+fn depth_of_field_camera(scene: &Scene) -> Camera {
+    let pose = CameraPose { origin: [20.0, 8.0, 14.0], looking_at: [28.0, 24.0, 8.0], fov_y: 60.0, aperture_radius: 0.4, ..Default::default() };
+    let mut camera = camera_from_pose(&pose);
+    camera.focus_distance = scene.autofocus(&camera).expect("No voxel in the center of the screen to focus on");
+    camera
+}
+
+const DEPTH_OF_FIELD_SAMPLES: u32 = 16;
+//On the software adapter this goes over PTRender::SOFTWARE_LOOP_LIMIT, so only the cpu tracer checks it there.
+const SUPERSAMPLING: u32 = 3;
+
+//Only the path tracer supports these projections, so they are not part of poses().
+fn projection_poses() -> [(&'static str, CameraPose); 2] {
+    [
+        ("map", CameraPose { origin: [32.0, 32.0, 40.0], looking_at: [32.0, 32.0, 0.0], projection: Projection::Orthographic, ortho_height: 64.0, ..Default::default() }),
+        ("panorama", CameraPose { origin: [32.0, 32.0, 16.0], looking_at: [40.0, 32.0, 16.0], projection: Projection::Equirectangular, ..Default::default() }),
+    ]
+}
+
+//Swings around the close up while rolling, sampled between keyframes so both the spline and the slerp show up in the image.
+fn camera_path() -> (CameraPath, f32) {
+    let keyframe = |time, origin, yaw: f32, pitch: f32, roll: f32| CameraKeyframe {
+        time,
+        origin,
+        yaw: yaw.to_radians(),
+        pitch: pitch.to_radians(),
+        roll: roll.to_radians(),
+        fov_y: 60.0,
+    };
+    let path = CameraPath {
+        keyframes: vec![
+            keyframe(0.0, [12.0, 4.0, 18.0], 45.0, -20.0, 0.0),
+            keyframe(1.0, [20.0, 8.0, 14.0], 63.0, -15.0, 10.0),
+            keyframe(3.0, [36.0, 8.0, 16.0], 110.0, -25.0, -20.0),
+        ],
+    };
+    (path, 1.6)
+}
+
+fn camera_from_pose(pose: &CameraPose) -> Camera {
+    Camera::from_pose(pose, SIZE)
+}
+
+struct Image {
+    width: usize,
+    height: usize,
+    rgb: Vec<u8>, //Top row first.
+}
+
+impl Image {
+    fn from_render(image: &RenderImage) -> Self {
+        Self { width: image.x_size, height: image.y_size, rgb: image.to_srgb8() }
+    }
+
+    fn save_png(&self, path: &PathBuf) {
+        let mut encoder = png::Encoder::new(BufWriter::new(File::create(path).unwrap()), self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header().unwrap().write_image_data(&self.rgb).unwrap();
+    }
+
+    fn load_png(path: &PathBuf) -> Option<Self> {
+        let decoder = png::Decoder::new(File::open(path).ok()?);
+        let mut reader = decoder.read_info().unwrap();
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).unwrap();
+        assert_eq!(info.color_type, png::ColorType::Rgb, "{} is not an 8-bit rgb png", path.display());
+
+        Some(Self {
+            width: info.width as usize,
+            height: info.height as usize,
+            rgb: buffer[..info.buffer_size()].to_vec(),
+        })
+    }
+}
+
+fn srgb8_to_lab(rgb: &[u8]) -> [f32; 3] {
+    let linear: Vec<f32> = rgb.iter().map(|c| {
+        let c = *c as f32 / 255.0;
+        if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+    }).collect();
+
+    //Linear sRGB to XYZ, normalized by the D65 white point.
+    let x = (0.4124 * linear[0] + 0.3576 * linear[1] + 0.1805 * linear[2]) / 0.95047;
+    let y = 0.2126 * linear[0] + 0.7152 * linear[1] + 0.0722 * linear[2];
+    let z = (0.0193 * linear[0] + 0.1192 * linear[1] + 0.9505 * linear[2]) / 1.08883;
+
+    let f = |t: f32| if t > 0.008856 { t.cbrt() } else { 7.787 * t + 16.0 / 116.0 };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+fn delta_e(a: &[u8], b: &[u8]) -> f32 {
+    let (a, b) = (srgb8_to_lab(a), srgb8_to_lab(b));
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+}
+
+fn check_golden(name: &str, actual: &Image) {
+    let reference_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(format!("{}.png", name));
+
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        actual.save_png(&reference_path);
+        return;
+    }
+
+    let diff_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("golden-diff");
+    std::fs::create_dir_all(&diff_dir).unwrap();
+    let actual_path = diff_dir.join(format!("{}_actual.png", name));
+
+    let Some(reference) = Image::load_png(&reference_path) else {
+        actual.save_png(&actual_path);
+        panic!("Missing reference {}, run with UPDATE_GOLDEN=1 to create it", reference_path.display());
+    };
+
+    if (reference.width, reference.height) != (actual.width, actual.height) {
+        actual.save_png(&actual_path);
+        panic!("{}: size {}x{} does not match reference {}x{}", name, actual.width, actual.height, reference.width, reference.height);
+    }
+
+    //Diff image: the reference in dimmed grey, with differing pixels in red scaled by how different they are.
+    let mut diff = Image { width: actual.width, height: actual.height, rgb: vec![0; actual.rgb.len()] };
+    let mut differing = 0;
+
+    for ((a, r), d) in actual.rgb.chunks_exact(3).zip(reference.rgb.chunks_exact(3)).zip(diff.rgb.chunks_exact_mut(3)) {
+        let delta = delta_e(a, r);
+        if delta > MAX_DELTA_E {
+            differing += 1;
+            d.copy_from_slice(&[(128.0 + delta * 4.0).min(255.0) as u8, 0, 0]);
+        } else {
+            let grey = ((r[0] as u32 + r[1] as u32 + r[2] as u32) / 9) as u8;
+            d.copy_from_slice(&[grey, grey, grey]);
+        }
+    }
+
+    let fraction = differing as f32 / (actual.width * actual.height) as f32;
+    if fraction > MAX_DIFFERING_FRACTION {
+        let diff_path = diff_dir.join(format!("{}_diff.png", name));
+        actual.save_png(&actual_path);
+        diff.save_png(&diff_path);
+        panic!(
+            "{}: {:.2}% of pixels differ from the reference (allowed {:.2}%), see {} and {}",
+            name, fraction * 100.0, MAX_DIFFERING_FRACTION * 100.0, actual_path.display(), diff_path.display()
+        );
+    }
+}
+
+//Without a software adapter the gpu tests pass without checking anything, so CI should set REQUIRE_GPU=1 to fail them instead.
+fn software_context() -> Option<RenderContext> {
+    match pollster::block_on(RenderContext::headless(true)) {
+        Ok(context) => Some(context),
+        Err(e) if std::env::var_os("REQUIRE_GPU").is_some() => panic!("No software adapter for the gpu golden images: {}", e),
+        Err(e) => {
+            print_skipped(&format!("gpu golden images, no software adapter: {}", e));
+            None
+        }
+    }
+}
+
+//Straight to stderr, the test harness only captures the print macros so this shows up even when the test passes.
+fn print_skipped(what: &str) {
+    let _ = std::io::stderr().write_all(format!("SKIPPED {}\n", what).as_bytes());
+}
+
+#[test]
+fn path_tracer_cpu() {
+    let scene = Scene::with_seed(SEED);
+
+    for (name, pose) in poses().into_iter().chain(projection_poses()) {
+        let image = camera_from_pose(&pose).render_scene_cpu(&scene);
+        check_golden(
+            &format!("path_tracer_{}", name),
+            &Image::from_render(&image),
+        );
+    }
+}
+
+//Climate shrunk down so a single chunk goes through every biome.
+#[test]
+fn path_tracer_cpu_biomes() {
+    let config = TerrainConfig { climate_scale: 24.0, warp_strength: 0.0, ..Default::default() };
+    let scene = Scene::with_generator(&NoiseTerrain::new(SEED, config));
+
+    for (name, pose) in [poses()[0], projection_poses()[0]] {
+        let image = camera_from_pose(&pose).render_scene_cpu(&scene);
+        check_golden(
+            &format!("path_tracer_biomes_{}", name),
+            &Image::from_render(&image),
+        );
+    }
+}
+
+//Caves reaching up to the surface, so they can be looked into from above.
+#[test]
+fn path_tracer_cpu_caves() {
+    let caves = CaveConfig { cheese_threshold: 0.3, cheese_roof: 0, ..Default::default() };
+    let config = TerrainConfig { biomes: vec![], caves, ..Default::default() };
+    let scene = Scene::with_generator(&NoiseTerrain::new(SEED, config));
+
+    let (_, pose) = &poses()[0];
+    let image = camera_from_pose(pose).render_scene_cpu(&scene);
+    check_golden(
+        "path_tracer_caves",
+        &Image::from_render(&image),
+    );
+}
+
+//Rivers and lakes close together, so chunk (0, 0) has both.
+#[test]
+fn path_tracer_cpu_water() {
+    let water = WaterConfig { river_scale: 96.0, lake_spacing: 64, lake_chance: 1.0, ..Default::default() };
+    let config = TerrainConfig { biomes: vec![], water, ..Default::default() };
+    let scene = Scene::with_generator(&NoiseTerrain::new(SEED, config));
+
+    for (name, pose) in [poses()[0], projection_poses()[0]] {
+        let image = camera_from_pose(&pose).render_scene_cpu(&scene);
+        check_golden(
+            &format!("path_tracer_water_{}", name),
+            &Image::from_render(&image),
+        );
+    }
+}
+
+#[test]
+fn path_tracer_cpu_camera_path() {
+    let scene = Scene::with_seed(SEED);
+    let (path, time) = camera_path();
+    let mut camera = camera_from_pose(&CameraPose::default());
+    path.apply(time, &mut camera);
+
+    let image = camera.render_scene_cpu(&scene);
+    check_golden(
+        "path_tracer_camera_path",
+        &Image::from_render(&image),
+    );
+}
+
+#[test]
+fn path_tracer_cpu_debug_views() {
+    let scene = Scene::with_seed(SEED);
+    let (_, pose) = &poses()[0];
+    let camera = camera_from_pose(pose);
+
+    for (name, view) in [("normals", DebugView::Normals), ("boxes_hit", DebugView::BoxesHit)] {
+        let tracer = CpuTracer {
+            params: ComputeParams::new(scene.cubes.len(), scene.background_rgba, SIZE),
+            camera: camera.uniform(),
+            cubes: &scene.cubes,
+            debug_view: DebugUniform::new(view, CHUNK_SIZE as f32),
+        };
+        let image = tracer.render();
+        check_golden(
+            &format!("path_tracer_debug_{}", name),
+            &Image::from_render(&image),
+        );
+    }
+}
+
+#[test]
+fn path_tracer_cpu_depth_of_field() {
+    let scene = Scene::with_seed(SEED);
+    let camera = depth_of_field_camera(&scene);
+
+    let mut image = RenderImage::new(SIZE[0], SIZE[1]);
+    for sample_index in 0..DEPTH_OF_FIELD_SAMPLES {
+        let tracer = CpuTracer {
+            params: ComputeParams { sample_index, jitter: 1, ..ComputeParams::new(scene.cubes.len(), scene.background_rgba, SIZE) },
+            camera: camera.uniform(),
+            cubes: &scene.cubes,
+            debug_view: DebugUniform::new(DebugView::Off, CHUNK_SIZE as f32),
+        };
+        tracer.render_sample(&mut image);
+    }
+    check_golden(
+        "path_tracer_depth_of_field",
+        &Image::from_render(&image),
+    );
+}
+
+#[test]
+fn path_tracer_cpu_supersampled() {
+    let scene = Scene::with_seed(SEED);
+    let (_, pose) = &poses()[0];
+
+    let tracer = CpuTracer {
+        params: ComputeParams { supersampling: SUPERSAMPLING, ..ComputeParams::new(scene.cubes.len(), scene.background_rgba, SIZE) },
+        camera: camera_from_pose(pose).uniform(),
+        cubes: &scene.cubes,
+        debug_view: DebugUniform::new(DebugView::Off, CHUNK_SIZE as f32),
+    };
+    let image = tracer.render();
+    check_golden(
+        "path_tracer_supersampled",
+        &Image::from_render(&image),
+    );
+}
+
+//Without pixels there is nothing to render, which shouldn't panic.
+#[test]
+fn path_tracer_cpu_empty_image() {
+    let scene = Scene::with_seed(SEED);
+    for size in [[0, 0], [0, SIZE[1]], [SIZE[0], 0]] {
+        let tracer = CpuTracer {
+            params: ComputeParams::new(scene.cubes.len(), scene.background_rgba, size),
+            camera: camera_from_pose(&poses()[0].1).uniform(),
+            cubes: &scene.cubes,
+            debug_view: DebugUniform::new(DebugView::Off, CHUNK_SIZE as f32),
+        };
+        assert!(tracer.render().pixels.is_empty());
+    }
+}
+
+//Compared against the same references as the cpu tracer, so this also checks that both stay in sync.
+#[test]
+fn path_tracer_gpu() {
+    let Some(context) = software_context() else { return };
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        return;
+    }
+
+    let (device, queue) = (&context.device, &context.queue);
+    let mut pt_render = PTRender::with_scene(device, queue, wgpu::TextureFormat::Rgba8UnormSrgb, SIZE, Scene::with_seed(SEED));
+
+    for (name, pose) in poses().into_iter().chain(projection_poses()) {
+        pt_render.camera = camera_from_pose(&pose);
+        pt_render.update_camera_uniform(queue);
+        pt_render.dispatch_compute(device, queue);
+
+        let image = pt_render.capture(device, queue);
+        check_golden(
+            &format!("path_tracer_{}", name),
+            &Image::from_render(&image),
+        );
+    }
+
+    let (_, pose) = &poses()[0];
+    pt_render.camera = camera_from_pose(pose);
+    pt_render.update_camera_uniform(queue);
+    pt_render.set_supersampling(queue, SUPERSAMPLING);
+    if let Some(warning) = pt_render.software_limit_warning(&context) {
+        print_skipped(&format!("path_tracer_supersampled on the gpu, {}", warning));
+    } else {
+        pt_render.dispatch_compute(device, queue);
+        let image = pt_render.capture(device, queue);
+        check_golden(
+            "path_tracer_supersampled",
+            &Image::from_render(&image),
+        );
+    }
+    pt_render.set_supersampling(queue, 1);
+
+    pt_render.camera = depth_of_field_camera(&pt_render.scene);
+    pt_render.update_camera_uniform(queue);
+    pt_render.set_jitter(queue, true);
+    for sample_index in 0..DEPTH_OF_FIELD_SAMPLES {
+        pt_render.set_sample_index(queue, sample_index);
+        pt_render.dispatch_compute(device, queue);
+    }
+    let image = pt_render.capture(device, queue);
+    check_golden(
+        "path_tracer_depth_of_field",
+        &Image::from_render(&image),
+    );
+}
+
+fn render_rasterizer(context: &RenderContext, object_group: &ObjectGroup, camera: &CameraBinding) -> Image {
+    let (device, queue) = (&context.device, &context.queue);
+    let [width, height] = [SIZE[0] as u32, SIZE[1] as u32];
+
+    let target = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Golden image target"),
+        size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    let view = target.create_view(&wgpu::TextureViewDescriptor::default());
+    let depth_texture = Texture::create_depth_texture(device, [width, height], "Golden image depth");
+
+    //Rows of a texture copy have to be aligned to 256 bytes.
+    let padded_bytes_per_row = (width * 4).div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT) * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    let readback = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Golden image readback"),
+        size: (padded_bytes_per_row * height) as u64,
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Golden image pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color { r: 0.1, g: 0.1, b: 0.1, a: 1.0 }),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &depth_texture.view,
+                depth_ops: Some(wgpu::Operations { load: wgpu::LoadOp::Clear(1.0), store: wgpu::StoreOp::Store }),
+                stencil_ops: None,
+            }),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        object_group.draw(&mut render_pass, camera);
+    }
+    encoder.copy_texture_to_buffer(
+        target.as_image_copy(),
+        wgpu::ImageCopyBuffer {
+            buffer: &readback,
+            layout: wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(padded_bytes_per_row), rows_per_image: Some(height) },
+        },
+        wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+    );
+    queue.submit(Some(encoder.finish()));
+
+    let slice = readback.slice(..);
+    slice.map_async(wgpu::MapMode::Read, |result| result.unwrap());
+    device.poll(wgpu::Maintain::Wait);
+
+    let mut rgb = Vec::with_capacity((width * height * 3) as usize);
+    for row in slice.get_mapped_range().chunks_exact(padded_bytes_per_row as usize) {
+        for pixel in row[..(width * 4) as usize].chunks_exact(4) {
+            rgb.extend_from_slice(&pixel[..3]);
+        }
+    }
+
+    Image { width: SIZE[0], height: SIZE[1], rgb }
+}
+
+#[test]
+fn rasterizer_gpu() {
+    let Some(context) = software_context() else { return };
+    let device = &context.device;
+
+    for (name, pose) in poses() {
+        let camera = CameraBinding::new(device, &camera_from_pose(&pose));
+        let objects = vec![Object::new_chunk(device, &NoiseTerrain::with_seed(SEED).generate_chunk(0, 0))];
+        let object_group = ObjectGroup::from_objects(device, wgpu::TextureFormat::Rgba8UnormSrgb, &camera, objects);
+
+        check_golden(&format!("rasterizer_{}", name), &render_rasterizer(&context, &object_group, &camera));
+    }
+}
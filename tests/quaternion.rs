@@ -0,0 +1,109 @@
+//Property tests for the Quaternion math, random rotations checked against identities that have to hold for all of them.
+
+use proptest::prelude::*;
+use ultimate_voxel_engine::path_tracing::{quaternion::Quaternion, vector_funcs::{cross_vector, dot_vector, normalize_vector, vector_length}};
+
+const EPSILON: f32 = 1e-4;
+const PI: f32 = std::f32::consts::PI;
+
+fn vector() -> impl Strategy<Value = [f32; 3]> {
+    [-10.0f32..10.0, -10.0f32..10.0, -10.0f32..10.0]
+}
+
+fn direction() -> impl Strategy<Value = [f32; 3]> {
+    vector().prop_filter("too short to normalize", |v| vector_length(v) > 0.1).prop_map(|v| normalize_vector(&v))
+}
+
+fn rotation() -> impl Strategy<Value = Quaternion> {
+    (direction(), -PI..PI).prop_map(|(axis, angle)| Quaternion::from_axis_angle(axis, angle))
+}
+
+fn assert_vector_eq(a: [f32; 3], b: [f32; 3]) {
+    for i in 0..3 {
+        assert!((a[i] - b[i]).abs() < EPSILON * (1.0 + b[i].abs()), "{:?} != {:?}", a, b);
+    }
+}
+
+//q and -q are the same rotation, so they compare equal here.
+fn assert_rotation_eq(a: Quaternion, b: Quaternion) {
+    assert!(a.dot(b).abs() > 1.0 - EPSILON, "{:?} != {:?}", a, b);
+}
+
+proptest! {
+    #[test]
+    fn rotation_keeps_length(q in rotation(), v in vector()) {
+        prop_assert!((vector_length(&(q * v)) - vector_length(&v)).abs() < EPSILON * (1.0 + vector_length(&v)));
+    }
+
+    #[test]
+    fn multiplying_composes_rotations(a in rotation(), b in rotation(), v in vector()) {
+        assert_vector_eq((a * b) * v, a * (b * v));
+    }
+
+    #[test]
+    fn inverse_undoes_rotation(q in rotation(), scale in 0.1f32..10.0, v in vector()) {
+        let scaled = Quaternion::new(q.w * scale, q.x * scale, q.y * scale, q.z * scale);
+        assert_rotation_eq(scaled * scaled.inverse(), Quaternion::IDENTITY);
+        assert_vector_eq(q.inverse() * (q * v), v);
+    }
+
+    #[test]
+    fn normalize_gives_unit_length(q in rotation(), scale in 0.1f32..10.0) {
+        let scaled = Quaternion::new(q.w * scale, q.x * scale, q.y * scale, q.z * scale);
+        prop_assert!((scaled.normalize().length() - 1.0).abs() < EPSILON);
+        assert_rotation_eq(scaled.normalize(), q);
+    }
+
+    #[test]
+    fn rotation_matrix_round_trips(q in rotation(), v in vector()) {
+        let m = q.to_rotation_matrix();
+        let by_matrix = [0, 1, 2].map(|row| m[0][row] * v[0] + m[1][row] * v[1] + m[2][row] * v[2]);
+        assert_vector_eq(by_matrix, q * v);
+        assert_rotation_eq(Quaternion::from_rotation_matrix(m), q);
+    }
+
+    #[test]
+    fn euler_round_trips(yaw in -3.1f32..3.1, pitch in -1.5f32..1.5, roll in -3.1f32..3.1) {
+        let [y, p, r] = Quaternion::from_euler(yaw, pitch, roll).to_euler();
+        assert_vector_eq([y, p, r], [yaw, pitch, roll]);
+    }
+
+    #[test]
+    fn euler_round_trips_rotation(q in rotation()) {
+        let [yaw, pitch, roll] = q.to_euler();
+        assert_rotation_eq(Quaternion::from_euler(yaw, pitch, roll), q);
+    }
+
+    #[test]
+    fn slerp_hits_endpoints(a in rotation(), b in rotation()) {
+        assert_rotation_eq(a.slerp(b, 0.0), a);
+        assert_rotation_eq(a.slerp(b, 1.0), b);
+        assert_rotation_eq(a.nlerp(b, 0.0), a);
+        assert_rotation_eq(a.nlerp(b, 1.0), b);
+    }
+
+    #[test]
+    fn slerp_rotates_at_constant_speed(a in rotation(), b in rotation(), t in 0.0f32..1.0) {
+        let q = a.slerp(b, t);
+        prop_assert!((q.length() - 1.0).abs() < EPSILON);
+
+        //The shortest arc between a and b is at most half a turn.
+        let total = (a.conjugate() * b).angle();
+        let total = total.min(2.0 * PI - total);
+        let travelled = (a.conjugate() * q).angle();
+        let travelled = travelled.min(2.0 * PI - travelled);
+        prop_assert!((travelled - t * total).abs() < 1e-3, "travelled {} of {} at t {}", travelled, total, t);
+    }
+
+    #[test]
+    fn look_rotation_looks_along_forward(forward in direction(), up in direction()) {
+        prop_assume!(vector_length(&cross_vector(&forward, &up)) > 0.01);
+        let q = Quaternion::look_rotation(forward, up);
+        assert_vector_eq(q * [1.0, 0.0, 0.0], forward);
+
+        //Left stays level with up, so up only tilts towards forward.
+        let left = q * [0.0, 1.0, 0.0];
+        prop_assert!(dot_vector(&left, &up).abs() < EPSILON, "left {:?} is not perpendicular to up {:?}", left, up);
+        prop_assert!(dot_vector(&(q * [0.0, 0.0, 1.0]), &up) > 0.0);
+    }
+}
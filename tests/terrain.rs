@@ -0,0 +1,394 @@
+//Chunks generated on their own have to fit together, the apron of one has to match the voxels of its neighbours.
+
+use std::{collections::HashSet, sync::Arc};
+
+use ultimate_voxel_engine::{
+    chunk_loader::{ChunkLoader, ChunkStreamer, LoadedChunk},
+    path_tracing::{chunk::{PTObject, SparseOctreeNode}, cube::Cube, scene::Scene},
+    terrain::{
+        biome::{biome_weights, default_biomes},
+        caves::{CaveConfig, Caves},
+        decoration::{DecorationConfig, PrefabConfig, PrefabModel},
+        water::{Water, WaterConfig},
+        Material, NoiseTerrain, TerrainConfig, TerrainGenerator, Voxel, VoxelChunk, CHUNK_HEIGHT, CHUNK_SIZE, MIN_Z,
+    },
+};
+
+const SEED: u32 = 31;
+
+#[test]
+fn apron_matches_neighbours() {
+    let terrain = NoiseTerrain::with_seed(SEED);
+    let chunk = terrain.generate_chunk(0, 0);
+
+    for (neighbour_x, neighbour_y) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+        let neighbour = terrain.generate_chunk(neighbour_x, neighbour_y);
+        for i in VoxelChunk::filled_range() {
+            for z in 0..CHUNK_HEIGHT {
+                //Column of the apron along the side facing the neighbour, and where the neighbour has it.
+                let (x, y) = match (neighbour_x, neighbour_y) {
+                    (1, 0) => (CHUNK_SIZE, i),
+                    (-1, 0) => (-1, i),
+                    (0, 1) => (i, CHUNK_SIZE),
+                    _ => (i, -1),
+                };
+                let local = [x, y, z];
+                let in_neighbour = [x - neighbour_x * CHUNK_SIZE, y - neighbour_y * CHUNK_SIZE, z];
+                assert_eq!(chunk.get(local), neighbour.get(in_neighbour), "chunks disagree at {:?}", local);
+            }
+        }
+    }
+}
+
+#[test]
+fn generation_is_deterministic() {
+    let a = NoiseTerrain::with_seed(SEED).generate_chunk(2, -1);
+    let b = NoiseTerrain::with_seed(SEED).generate_chunk(2, -1);
+    assert!(a.voxels == b.voxels);
+}
+
+//A prefab in every square, standing on the ground with nothing under it but the terrain.
+#[test]
+fn prefabs_stand_on_the_ground() {
+    const COLOR: [u8; 4] = [255, 0, 255, 255];
+    let model = PrefabModel { voxels: vec![([0, 0, 0], COLOR), ([0, 0, 1], COLOR), ([1, 0, 1], COLOR)] };
+    let prefab = PrefabConfig { chance: 1.0, model, ..Default::default() };
+    let decoration = DecorationConfig { tree_chance: 0.0, cactus_chance: 0.0, boulder_chance: 0.0, prefabs: vec![prefab], ..Default::default() };
+    let config = TerrainConfig { biomes: vec![], decoration, ..Default::default() };
+    let chunk = NoiseTerrain::new(SEED, config).generate_chunk(0, 0);
+
+    let mut bases = 0;
+    for y in 0..CHUNK_SIZE {
+        for x in 0..CHUNK_SIZE {
+            for z in 1..CHUNK_HEIGHT - 1 {
+                let is_prefab = |z| chunk.get([x, y, z]).color == COLOR;
+                if is_prefab(z) && !is_prefab(z - 1) && is_prefab(z + 1) {
+                    bases += 1;
+                    assert!(!chunk.get([x, y, z - 1]).is_air(), "prefab at {:?} is floating", [x, y, z]);
+                }
+            }
+        }
+    }
+    assert!(bases > 0, "no prefabs were placed");
+}
+
+//Climate points in a grid covering the whole climate map and a bit past it.
+fn climates() -> impl Iterator<Item = (f64, f64)> {
+    (-12..=12).flat_map(|t| (-12..=12).map(move |h| (t as f64 / 10.0, h as f64 / 10.0)))
+}
+
+#[test]
+fn biome_weights_sum_to_one() {
+    let biomes = default_biomes();
+    for blend in [0.0, 0.05, 0.2, 10.0] {
+        for (temperature, humidity) in climates() {
+            let weights = biome_weights(&biomes, temperature, humidity, blend);
+            let total: f64 = weights.iter().sum();
+            assert!((total - 1.0).abs() < 1e-9, "weights {:?} at {:?} with blend {} sum to {}", weights, (temperature, humidity), blend, total);
+            assert!(weights.iter().all(|&w| w >= 0.0));
+        }
+    }
+}
+
+//Further than blend from every other biome, the closest one gets everything. With a blend of 0 that is everywhere.
+#[test]
+fn closest_biome_takes_all_away_from_borders() {
+    let biomes = default_biomes();
+    for blend in [0.0, 0.05] {
+        for (temperature, humidity) in climates() {
+            let mut distances: Vec<(usize, f64)> = biomes.iter()
+                .map(|b| ((b.temperature - temperature).powi(2) + (b.humidity - humidity).powi(2)).sqrt())
+                .enumerate()
+                .collect();
+            distances.sort_by(|a, b| a.1.total_cmp(&b.1));
+            let (closest, distance) = distances[0];
+            if distances[1].1 - distance <= blend {
+                continue;
+            }
+
+            let weights = biome_weights(&biomes, temperature, humidity, blend);
+            for (i, &weight) in weights.iter().enumerate() {
+                assert_eq!(weight, if i == closest { 1.0 } else { 0.0 }, "weights {:?} at {:?} with blend {}", weights, (temperature, humidity), blend);
+            }
+        }
+    }
+}
+
+//The octrees cover the chunk and nothing else, with a leaf for every cube.
+#[test]
+fn octrees_match_cubes() {
+    fn leaves(node: &SparseOctreeNode) -> usize {
+        match &node.children {
+            Some(children) => children.iter().map(leaves).sum(),
+            None => 1,
+        }
+    }
+
+    let chunk = NoiseTerrain::with_seed(SEED).generate_chunk(1, -2);
+    let object = PTObject::new(&chunk);
+    let origin = chunk.origin();
+    for octree in &object.octrees {
+        let [min, max] = octree.aabb;
+        assert!((0..3).all(|i| max[i] - min[i] == CHUNK_SIZE), "octree {:?} isn't a cube of the chunk's width", octree.aabb);
+        assert!(min[0] == origin[0] && min[1] == origin[1] && min[2] >= origin[2] && max[2] <= origin[2] + CHUNK_HEIGHT);
+    }
+    assert_eq!(object.octrees.iter().map(|octree| leaves(&octree.root)).sum::<usize>(), object.cubes.len());
+}
+
+//Without rivers, lakes and structures the surface is the height map's and nothing gets built into the caves.
+fn bare_config(caves: CaveConfig) -> TerrainConfig {
+    let water = WaterConfig { river_width: 0.0, lake_chance: 0.0, ..Default::default() };
+    let decoration = DecorationConfig { tree_chance: 0.0, cactus_chance: 0.0, boulder_chance: 0.0, ..Default::default() };
+    TerrainConfig { caves, water, decoration, ..Default::default() }
+}
+
+//Calls visit with every voxel of the chunk the caves carve out of the ground, with its local position.
+fn for_carved(terrain: &NoiseTerrain, chunk: &VoxelChunk, mut visit: impl FnMut([i32; 3])) {
+    let caves = Caves::new(terrain.seed, terrain.config.caves);
+    let origin = chunk.origin();
+    for y in 0..CHUNK_SIZE {
+        for x in 0..CHUNK_SIZE {
+            let surface = terrain.height_at(origin[0] + x, origin[1] + y).clamp(MIN_Z, MIN_Z + CHUNK_HEIGHT - 1);
+            for z in MIN_Z..=caves.max_ground(surface).min(MIN_Z + CHUNK_HEIGHT - 1) {
+                let point = [origin[0] + x, origin[1] + y, z];
+                if caves.is_ground(point, surface) && caves.is_carved(point, surface) {
+                    visit([x, y, z - MIN_Z]);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn caves_are_air() {
+    let caves = CaveConfig { cheese_threshold: 0.3, ..Default::default() };
+    let terrain = NoiseTerrain::new(SEED, bare_config(caves));
+    let chunk = terrain.generate_chunk(0, 1);
+
+    let mut carved = 0;
+    for_carved(&terrain, &chunk, |local| {
+        assert!(chunk.get(local).is_air(), "carved voxel at {:?} is {:?}", local, chunk.get(local));
+        carved += 1;
+    });
+    assert!(carved > 0, "no caves were carved");
+}
+
+//The open air below the sea and the lakes is water all the way down to the ground.
+#[test]
+fn water_fills_up_to_its_level() {
+    let caves = CaveConfig { overhang_strength: 0.0, cheese_threshold: 1.0, tunnel_width: 0.0, ..Default::default() };
+    let water = WaterConfig { lake_chance: 1.0, ..Default::default() };
+    let config = TerrainConfig { water, ..bare_config(caves) };
+    let terrain = NoiseTerrain::new(SEED, config);
+    let water = Water::new(SEED, terrain.config.water);
+    let lakes = water.lakes_near([-512, -512], [512, 512], |x, y| terrain.height_at(x, y));
+    let lake = lakes.iter().find(|lake| lake.level >= terrain.config.sea_level).expect("no lake above the sea");
+
+    //The chunk with the lake's center in it, wherever the sea reaches into it that gets checked as well.
+    let chunk_x = (lake.center[0] / CHUNK_SIZE as f64).floor() as i32;
+    let chunk_y = (lake.center[1] / CHUNK_SIZE as f64).floor() as i32;
+    let chunk = terrain.generate_chunk(chunk_x, chunk_y);
+    let origin = chunk.origin();
+
+    let mut lake_water = 0;
+    for y in 0..CHUNK_SIZE {
+        for x in 0..CHUNK_SIZE {
+            let (wx, wy) = (origin[0] + x, origin[1] + y);
+            let (surface, water_top) = water.carve(wx, wy, terrain.height_at(wx, wy), &lakes, terrain.config.sea_level);
+            for z in (surface + 1).max(MIN_Z)..=water_top.min(MIN_Z + CHUNK_HEIGHT - 1) {
+                let voxel = chunk.get([x, y, z - MIN_Z]);
+                assert_eq!(voxel, Material::Water.voxel(SEED, [wx, wy, z]), "no water at {:?}", [wx, wy, z]);
+                if z >= terrain.config.sea_level {
+                    lake_water += 1;
+                }
+            }
+        }
+    }
+    assert!(lake_water > 0, "the lake has no water above the sea");
+}
+
+//Caves are carved out of the ground, the sea above them doesn't leak in.
+#[test]
+fn caves_under_water_stay_dry() {
+    let caves = CaveConfig { cheese_threshold: 0.3, ..Default::default() };
+    let terrain = NoiseTerrain::new(SEED, TerrainConfig { sea_level: 30, ..bare_config(caves) });
+    let chunk = terrain.generate_chunk(0, 1);
+
+    let mut under_water = 0;
+    for_carved(&terrain, &chunk, |local| {
+        assert!(chunk.get(local).is_air(), "carved voxel at {:?} is {:?}", local, chunk.get(local));
+        if local[2] + MIN_Z < terrain.config.sea_level {
+            under_water += 1;
+        }
+    });
+    assert!(under_water > 0, "no caves under the sea");
+}
+
+//A lake's water stays below every point its level was picked from, so it never spills over its shore.
+#[test]
+fn lakes_stay_below_their_shore() {
+    let terrain = NoiseTerrain::with_seed(SEED);
+    let water = Water::new(SEED, WaterConfig { lake_chance: 1.0, ..Default::default() });
+    let lakes = water.lakes_near([-512, -512], [512, 512], |x, y| terrain.height_at(x, y));
+    assert!(!lakes.is_empty());
+
+    for lake in &lakes {
+        for i in 0..Water::SHORE_SAMPLES {
+            let angle = i as f64 / Water::SHORE_SAMPLES as f64 * std::f64::consts::TAU;
+            let x = (lake.center[0] + lake.radius * angle.cos()) as i32;
+            let y = (lake.center[1] + lake.radius * angle.sin()) as i32;
+            assert!(lake.level < terrain.height_at(x, y), "{:?} reaches its shore at {:?}", lake, [x, y]);
+        }
+    }
+}
+
+//Rock with solid voxels on every side can't be seen, so it doesn't get a leaf in the octree.
+#[test]
+fn octrees_leave_out_enclosed_rock() {
+    //Local positions of the leaves, the children of a node are in the order of the bits of its child_mask.
+    fn leaves(node: &SparseOctreeNode, min: [i32; 3], size: i32, found: &mut HashSet<[i32; 3]>) {
+        let Some(children) = &node.children else {
+            found.insert(min);
+            return;
+        };
+        let mask = node.child_mask.unwrap();
+        let half = size / 2;
+        let mut children = children.iter();
+        for child in (0..8).filter(|child| mask & (1 << child) != 0) {
+            let offset = [child & 1, (child >> 1) & 1, child >> 2].map(|c| c * half);
+            let child_min = [min[0] + offset[0], min[1] + offset[1], min[2] + offset[2]];
+            leaves(children.next().unwrap(), child_min, half, found);
+        }
+    }
+
+    let chunk = NoiseTerrain::with_seed(SEED).generate_chunk(0, 1);
+    let object = PTObject::new(&chunk);
+    let origin = chunk.origin();
+    let mut found = HashSet::new();
+    for octree in &object.octrees {
+        let min = octree.aabb[0];
+        leaves(&octree.root, [min[0] - origin[0], min[1] - origin[1], min[2] - origin[2]], CHUNK_SIZE, &mut found);
+    }
+
+    let mut enclosed = 0;
+    for z in 0..CHUNK_HEIGHT {
+        for y in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                let local = [x, y, z];
+                let solid = !chunk.get(local).is_air();
+                if solid && !chunk.is_visible(local) {
+                    enclosed += 1;
+                }
+                assert_eq!(found.contains(&local), solid && chunk.is_visible(local), "wrong leaf at {:?}", local);
+            }
+        }
+    }
+    assert!(enclosed > 0, "no enclosed rock to leave out");
+}
+
+//Built on a worker, a chunk has to come out the same as one built on the calling thread.
+#[test]
+fn loader_matches_generator() {
+    let terrain = Arc::new(NoiseTerrain::with_seed(SEED));
+    let loader = ChunkLoader::new(terrain.clone(), 2);
+    loader.request([[0, 0], [-1, 2]]);
+
+    for _ in 0..2 {
+        let chunk = loader.wait().unwrap();
+        let expected = LoadedChunk::new(terrain.as_ref(), chunk.position);
+        let bytes = |loaded: &LoadedChunk| {
+            [bytemuck::cast_slice::<_, u8>(&loaded.object.cubes).to_vec(), bytemuck::cast_slice(&loaded.mesh.vertices).to_vec()]
+        };
+        assert!(bytes(&chunk) == bytes(&expected), "{:?} differs", chunk.position);
+        assert_eq!(chunk.mesh.indices, expected.mesh.indices);
+    }
+}
+
+#[test]
+fn loader_builds_closest_first() {
+    let loader = ChunkLoader::new(Arc::new(NoiseTerrain::with_seed(SEED)), 1);
+    loader.set_focus([2.5 * CHUNK_SIZE as f32, 0.5 * CHUNK_SIZE as f32, 0.0]);
+    loader.request([[-2, 0], [0, 0], [5, 0], [2, 0], [1, 0]]);
+
+    let order: Vec<[i32; 2]> = (0..5).map(|_| loader.wait().unwrap().position).collect();
+    assert_eq!(order, [[2, 0], [1, 0], [0, 0], [5, 0], [-2, 0]]);
+}
+
+//A single voxel per chunk, so streaming a lot of chunks stays quick.
+struct SingleVoxel;
+
+impl TerrainGenerator for SingleVoxel {
+    fn seed(&self) -> u32 {
+        0
+    }
+
+    fn generate_chunk(&self, chunk_x: i32, chunk_y: i32) -> VoxelChunk {
+        let mut chunk = VoxelChunk::new(chunk_x, chunk_y);
+        chunk.set([0, 0, 0], Voxel::from_rgb([255, 255, 255]));
+        chunk
+    }
+}
+
+//Updates the streamer until everything in range came in, keeping track of the chunks like a renderer would.
+fn stream(streamer: &mut ChunkStreamer, focus: [f32; 3], loaded: &mut HashSet<[i32; 2]>) {
+    loop {
+        let update = streamer.update(focus);
+        for position in update.unloaded {
+            assert!(loaded.remove(&position), "{:?} was unloaded without being loaded", position);
+        }
+        for chunk in update.loaded {
+            assert!(loaded.insert(chunk.position), "{:?} was loaded twice", chunk.position);
+        }
+        if streamer.loading() == 0 {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(5));
+    }
+}
+
+#[test]
+fn streamer_follows_the_focus() {
+    let mut streamer = ChunkStreamer::new(ChunkLoader::new(Arc::new(SingleVoxel), 2), 1);
+    let mut loaded = HashSet::new();
+    let square = |center: [i32; 2]| -> HashSet<[i32; 2]> {
+        (-1..=1).flat_map(|y| (-1..=1).map(move |x| [center[0] + x, center[1] + y])).collect()
+    };
+
+    stream(&mut streamer, [1.0, 1.0, 0.0], &mut loaded);
+    assert_eq!(loaded, square([0, 0]));
+
+    //Past the origin, where the chunk coordinates go negative.
+    let size = CHUNK_SIZE as f32;
+    stream(&mut streamer, [-1.5 * size, -0.5 * size, 0.0], &mut loaded);
+    assert_eq!(loaded, square([-2, -1]));
+}
+
+//Chunks far apart share a slot in the grid, the newest one takes it over.
+#[test]
+fn scene_grid_wraps_around() {
+    let object = |position: [i32; 2], amount: usize| PTObject {
+        cubes: vec![Cube::new_cube_at(&[position[0] as f32, position[1] as f32, 0.0], [1.0; 4]); amount],
+        octrees: vec![],
+    };
+    let positions = |scene: &Scene| -> Vec<[f32; 2]> { scene.cubes.iter().map(|cube| [cube.min[0], cube.min[1]]).collect() };
+
+    let mut scene = Scene::with_render_distance(1);
+    scene.add_chunk([-1, -1], &object([-1, -1], 2));
+    scene.add_chunk([0, 0], &object([0, 0], 3));
+    scene.add_chunk([1, 1], &object([1, 1], 1));
+    assert_eq!(scene.changed_from, Some(0));
+
+    scene.changed_from = None;
+    assert!(scene.remove_chunk([0, 0]));
+    assert!(!scene.remove_chunk([0, 0]));
+    assert_eq!(scene.changed_from, Some(2));
+    assert_eq!(positions(&scene), [[-1.0, -1.0], [-1.0, -1.0], [1.0, 1.0]]);
+
+    //Three chunks along x from [-1, -1], so in the same slot of a grid of 3 by 3.
+    scene.changed_from = None;
+    scene.add_chunk([2, -1], &object([2, -1], 1));
+    assert!(!scene.is_loaded([-1, -1]));
+    assert!(scene.is_loaded([2, -1]) && scene.is_loaded([1, 1]));
+    assert_eq!(scene.changed_from, Some(0));
+    assert_eq!(positions(&scene), [[1.0, 1.0], [2.0, -1.0]]);
+}